@@ -1,3 +1,4 @@
+use super::events::EventKind;
 use super::memory::MemoryBus;
 
 use std::f32::consts::PI;
@@ -48,6 +49,7 @@ fn make_tnd_table() -> [f32; 203] {
 
 /// Represents a first order filter, implementing the following formula:
 /// y_n = b0 * x_n + b1 * x_(n-1) - a y_(n-1)
+#[derive(Clone)]
 struct Filter {
     b0: f32,
     b1: f32,
@@ -98,6 +100,7 @@ impl Filter {
 }
 
 /// Represents the collection of filters applied to the output of the APU
+#[derive(Clone)]
 struct FilterChain {
     high1: Filter,
     high2: Filter,
@@ -121,6 +124,7 @@ impl FilterChain {
 }
 
 /// Represents the Square signal generator of the APU
+#[derive(Clone)]
 struct Square {
     /// Whether or not this generator is turned on
     enabled: bool,
@@ -305,6 +309,7 @@ impl Square {
 }
 
 /// Represents the triangle signal simulator
+#[derive(Clone)]
 struct Triangle {
     /// Whether or not output is enabled
     enabled: bool,
@@ -396,6 +401,7 @@ impl Triangle {
 }
 
 /// Represents the noise signal generator
+#[derive(Clone)]
 struct Noise {
     /// Whether or not output is enabled for this component
     enabled: bool,
@@ -513,6 +519,7 @@ impl Noise {
 }
 
 /// Generator for DMC Samples
+#[derive(Clone)]
 struct DMC {
     /// Whether or not output is enabled for this generator
     enabled: bool,
@@ -595,6 +602,10 @@ impl DMC {
         }
     }
 
+    fn wants_sample(&self) -> bool {
+        self.enabled && self.current_length > 0 && self.bit_count == 0
+    }
+
     // returns whether or not to stall
     fn step_reader(&mut self, read: u8) -> bool {
         if self.current_length > 0 && self.bit_count == 0 {
@@ -634,6 +645,7 @@ impl DMC {
 }
 
 /// Contains registers that are written to across the memory bus
+#[derive(Clone)]
 pub struct APUState {
     /// The first square output generator
     square1: Square,
@@ -784,6 +796,7 @@ impl APUState {
 }
 
 /// Represents the audio processing unit
+#[derive(Clone)]
 pub(crate) struct APU {
     /// The chain of filters used on the output of the generators
     filter: FilterChain,
@@ -860,8 +873,12 @@ impl APU {
             m.apu.square1.step_timer();
             m.apu.square2.step_timer();
             m.apu.noise.step_timer();
-            let address = m.apu.dmc.current_address;
-            let read = m.cpu_read(address);
+            // The DMC only reads memory when it needs another sample
+            let read = if m.apu.dmc.wants_sample() {
+                m.read_sample(m.apu.dmc.current_address)
+            } else {
+                0
+            };
             if m.apu.dmc.step_timer(read) {
                 m.cpu.add_stall(4);
             }
@@ -910,6 +927,9 @@ impl APU {
     fn fire_irq(&self, m: &mut MemoryBus) {
         if m.apu.frame_irq {
             m.cpu.set_irq();
+            if let Some(events) = &mut m.events.0 {
+                events.record(EventKind::Irq);
+            }
         }
     }
 }
@@ -0,0 +1,384 @@
+//! A headless runner for NES roms.
+//!
+//! This loads a rom, runs it for a number of frames, optionally feeding it
+//! scripted input, and then writes out the last frame as a PNG, the audio as
+//! a WAV file, and the contents of RAM.
+extern crate ludus;
+
+use ludus::*;
+
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "\
+usage: ludus-run <rom.nes> [options]
+
+options:
+    --frames <n>          The number of frames to run for (default: 60)
+    --input <file>        A script of inputs to play
+    --png <file>          Write the last frame to this PNG file
+    --wav <file>          Write the audio to this WAV file
+    --ram <file>          Write the 2KB of RAM to this file at the end
+    --sample-rate <rate>  The sample rate for the audio (default: 44100)
+
+Input scripts contain lines of the form `<frame> <buttons...>`, where the
+buttons are held from that frame onwards, until the next line. Buttons are
+any of `a b select start up down left right`, or `-` for no buttons.
+Anything after a `#` is ignored.
+";
+
+/// The options passed on the command line
+struct Options {
+    rom: String,
+    frames: u32,
+    input: Option<String>,
+    png: Option<String>,
+    wav: Option<String>,
+    ram: Option<String>,
+    sample_rate: u32,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut rom = None;
+        let mut options = Options {
+            rom: String::new(),
+            frames: 60,
+            input: None,
+            png: None,
+            wav: None,
+            ram: None,
+            sample_rate: 44100,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--frames" => options.frames = parse_number(&value()?)?,
+                "--input" => options.input = Some(value()?),
+                "--png" => options.png = Some(value()?),
+                "--wav" => options.wav = Some(value()?),
+                "--ram" => options.ram = Some(value()?),
+                "--sample-rate" => options.sample_rate = parse_number(&value()?)?,
+                a if a.starts_with("--") => return Err(format!("unknown option {}", a)),
+                a if rom.is_none() => rom = Some(a.to_string()),
+                a => return Err(format!("unexpected argument {}", a)),
+            }
+        }
+        options.rom = rom.ok_or_else(|| "missing rom file".to_string())?;
+        if options.sample_rate == 0 {
+            return Err("the sample rate must be positive".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number(s: &str) -> Result<u32, String> {
+    s.parse().map_err(|_| format!("invalid number {}", s))
+}
+
+/// Parses an input script into a list of frames and the buttons
+/// to start holding on that frame, sorted by frame.
+fn parse_script(script: &str) -> Result<Vec<(u32, ButtonState)>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let frame = match words.next() {
+            None => continue,
+            Some(word) => parse_number(word).map_err(|e| format!("line {}: {}", i + 1, e))?,
+        };
+        let mut buttons = ButtonState::default();
+        for word in words {
+            match word.to_lowercase().as_str() {
+                "a" => buttons.a = true,
+                "b" => buttons.b = true,
+                "select" => buttons.select = true,
+                "start" => buttons.start = true,
+                "up" => buttons.up = true,
+                "down" => buttons.down = true,
+                "left" => buttons.left = true,
+                "right" => buttons.right = true,
+                "-" => {}
+                w => return Err(format!("line {}: unknown button {}", i + 1, w)),
+            }
+        }
+        entries.push((frame, buttons));
+    }
+    entries.sort_by_key(|&(frame, _)| frame);
+    Ok(entries)
+}
+
+/// Collects every sample the APU generates
+struct AudioRecorder(Vec<f32>);
+
+impl AudioDevice for AudioRecorder {
+    fn push_sample(&mut self, sample: f32) {
+        self.0.push(sample);
+    }
+}
+
+/// Keeps a copy of the last frame the PPU generated
+struct FrameRecorder(Vec<u32>);
+
+impl VideoDevice for FrameRecorder {
+    fn blit_pixels(&mut self, pixels: &PixelBuffer) {
+        self.0.clear();
+        self.0.extend_from_slice(pixels.as_ref());
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn push_chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Encodes ARGB pixels as an RGB PNG.
+///
+/// This doesn't bother compressing the image, and stores the
+/// pixels directly in the zlib stream.
+fn encode_png(pixels: &[u32], width: usize, height: usize) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for row in pixels.chunks(width) {
+        // No filtering for this row
+        raw.push(0);
+        for &argb in row {
+            let [_, r, g, b] = argb.to_be_bytes();
+            raw.extend_from_slice(&[r, g, b]);
+        }
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    while let Some(block) = blocks.next() {
+        let last = if blocks.peek().is_none() { 1 } else { 0 };
+        let len = block.len() as u16;
+        zlib.push(last);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit depth, RGB, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    push_chunk(&mut png, b"IHDR", &header);
+    push_chunk(&mut png, b"IDAT", &zlib);
+    push_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Encodes mono samples in the range [-1, 1] as a 16 bit PCM WAV file
+fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, with a single channel
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for &sample in samples {
+        let clamped = sample.clamp(-1.0, 1.0);
+        let value = (clamped * f32::from(i16::MAX)) as i16;
+        wav.extend_from_slice(&value.to_le_bytes());
+    }
+    wav
+}
+
+fn write_file(path: &str, bytes: &[u8]) -> Result<(), String> {
+    fs::write(path, bytes).map_err(|e| format!("failed to write {}: {}", path, e))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let rom = fs::read(&options.rom).map_err(|e| format!("failed to read {}: {}", options.rom, e))?;
+    let cart = Cart::from_bytes(&rom).map_err(|e| format!("failed to load rom: {:?}", e))?;
+    let script = match options.input {
+        None => Vec::new(),
+        Some(ref path) => {
+            let text =
+                fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+            parse_script(&text)?
+        }
+    };
+
+    let mut console = Console::new(cart, options.sample_rate);
+    let mut audio = AudioRecorder(Vec::new());
+    let mut video = FrameRecorder(vec![0; NES_WIDTH * NES_HEIGHT]);
+    let mut script = script.into_iter().peekable();
+    for frame in 0..options.frames {
+        while let Some(&(start, _)) = script.peek() {
+            if start > frame {
+                break;
+            }
+            if let Some((_, buttons)) = script.next() {
+                console.update_controller(buttons);
+            }
+        }
+        console.step_frame(&mut audio, &mut video);
+    }
+
+    if let Some(ref path) = options.png {
+        write_file(path, &encode_png(&video.0, NES_WIDTH, NES_HEIGHT))?;
+    }
+    if let Some(ref path) = options.wav {
+        write_file(path, &encode_wav(&audio.0, options.sample_rate))?;
+    }
+    if let Some(ref path) = options.ram {
+        write_file(path, console.ram())?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "--help" || a == "-h") {
+        print!("{}", USAGE);
+        return;
+    }
+    let result = Options::parse(&args).and_then(|options| run(&options));
+    if let Err(e) = result {
+        eprintln!("ludus-run: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_be(bytes: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    fn u32_le(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn scripts_are_sorted_by_frame() {
+        let script = "# A comment\n10 Right A\n\n0 - # Nothing yet\n";
+        let entries = parse_script(script).unwrap();
+        let right_a = ButtonState {
+            a: true,
+            right: true,
+            ..Default::default()
+        };
+        assert_eq!(entries, [(0, ButtonState::default()), (10, right_a)]);
+    }
+
+    #[test]
+    fn script_errors_name_the_line() {
+        assert_eq!(
+            parse_script("0 a\n5 jump"),
+            Err("line 2: unknown button jump".to_string())
+        );
+        assert_eq!(
+            parse_script("soon a"),
+            Err("line 1: invalid number soon".to_string())
+        );
+        assert_eq!(
+            parse_script("-1 b"),
+            Err("line 1: invalid number -1".to_string())
+        );
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn png_chunks_are_well_formed() {
+        let pixels = [0xFF11_2233, 0xFF44_5566, 0xFF77_8899, 0xFFAA_BBCC];
+        let png = encode_png(&pixels, 2, 2);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < png.len() {
+            let len = u32_be(&png, at) as usize;
+            let body = &png[at + 4..at + 8 + len];
+            assert_eq!(u32_be(&png, at + 8 + len), crc32(body));
+            chunks.push((&body[..4], &body[4..]));
+            at += 12 + len;
+        }
+        assert_eq!(at, png.len());
+        let kinds: Vec<_> = chunks.iter().map(|&(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, &[0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        // A single stored block, holding both rows with their filter byte
+        let raw = [0, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+        let raw = [&raw[..], &[0, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC]].concat();
+        let zlib = chunks[1].1;
+        assert_eq!(&zlib[..7], &[0x78, 0x01, 1, 14, 0, !14, 0xFF]);
+        assert_eq!(&zlib[7..21], &raw[..]);
+        assert_eq!(u32_be(zlib, 21), adler32(&raw));
+        assert!(chunks[2].1.is_empty());
+    }
+
+    #[test]
+    fn wav_header_describes_the_samples() {
+        let wav = encode_wav(&[0.0, 1.0, -2.0], 48000);
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32_le(&wav, 4), 36 + 6);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_le(&wav, 16), 16);
+        // PCM, mono, 48kHz, 2 bytes per sample, 16 bits
+        assert_eq!(&wav[20..24], &[1, 0, 1, 0]);
+        assert_eq!(u32_le(&wav, 24), 48000);
+        assert_eq!(u32_le(&wav, 28), 96000);
+        assert_eq!(&wav[32..36], &[2, 0, 16, 0]);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_le(&wav, 40), 6);
+        // Samples out of range get clamped
+        let samples: Vec<_> = wav[44..]
+            .chunks(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect();
+        assert_eq!(samples, [0, i16::MAX, -i16::MAX]);
+    }
+}
@@ -0,0 +1,284 @@
+//! Importing movies recorded with BizHawk.
+//!
+//! A `.bk2` file is a zip archive, containing a header, the sync settings
+//! of the core used to record it, and the input log. Only movies using
+//! standard controllers and starting from power on can be imported.
+//!
+//! More info: http://tasvideos.org/Bizhawk/BK2Format.html
+use crate::controller::ButtonState;
+use crate::movie::{Movie, MovieFrame};
+
+/// Represents the possible errors when importing a BK2 movie
+#[derive(Clone, Debug, PartialEq)]
+pub enum Bk2Error {
+    /// The file isn't a zip archive we know how to read
+    InvalidArchive,
+    /// A file in the archive uses a compression method other than deflate
+    UnsupportedCompression(u16),
+    /// The archive is missing a file every movie needs
+    MissingFile(&'static str),
+    /// The movie was recorded for a console other than the NES
+    UnsupportedPlatform(String),
+    /// The movie starts from a save state instead of power on
+    StartsFromSaveState,
+    /// The movie was recorded with a different rom
+    RomMismatch { expected: String, actual: String },
+    /// The input log uses a button we don't know how to emulate
+    UnsupportedController(String),
+    /// A line of the input log couldn't be parsed, with its line number
+    InvalidInput(usize),
+}
+
+/// A movie imported from BizHawk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bk2 {
+    /// The input of the movie, with the keys of the header
+    pub movie: Movie,
+    /// The sync settings of the core, as the JSON stored in the archive
+    pub sync_settings: String,
+}
+
+impl Bk2 {
+    /// Reads a BK2 archive, checking that it was recorded with this rom.
+    ///
+    /// The rom should be the contents of the `.nes` file, including the header.
+    pub fn from_bytes(bk2: &[u8], rom: &[u8]) -> Result<Bk2, Bk2Error> {
+        let archive = Archive::new(bk2)?;
+        let header = archive.read_text("Header.txt")?;
+        let mut movie = Movie::new();
+        for line in header.lines() {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find(' ') {
+                Some(space) => (&line[..space], &line[space + 1..]),
+                None => (line, ""),
+            };
+            movie.header.push((key.to_string(), value.to_string()));
+        }
+        if let Some(platform) = movie.header_value("Platform") {
+            if platform != "NES" {
+                return Err(Bk2Error::UnsupportedPlatform(platform.to_string()));
+            }
+        }
+        if movie
+            .header_value("StartsFromSavestate")
+            .is_some_and(|v| v.eq_ignore_ascii_case("true"))
+        {
+            return Err(Bk2Error::StartsFromSaveState);
+        }
+        if let Some(expected) = movie.header_value("SHA1") {
+            let actual = rom_hash(rom);
+            if !expected.eq_ignore_ascii_case(&actual) {
+                return Err(Bk2Error::RomMismatch {
+                    expected: expected.to_string(),
+                    actual,
+                });
+            }
+        }
+        let sync_settings = match archive.find("SyncSettings.json") {
+            Some(entry) => archive.read_text_entry(entry)?,
+            None => String::new(),
+        };
+        let input = archive.read_text("Input Log.txt")?;
+        movie.frames = parse_input_log(&input)?;
+        Ok(Bk2 {
+            movie,
+            sync_settings,
+        })
+    }
+}
+
+/// BizHawk identifies NES roms by the SHA1 of their contents, without the header
+fn rom_hash(rom: &[u8]) -> String {
+    let mut start = 16.min(rom.len());
+    // Roms with a trainer have 512 extra bytes before PRG
+    if rom.len() > 6 && rom[6] & 0b100 != 0 {
+        start = (start + 512).min(rom.len());
+    }
+    sha1_smol::Sha1::from(&rom[start..]).digest().to_string()
+}
+
+/// What a column of the input log controls
+#[derive(Clone, Copy)]
+enum Column {
+    Reset,
+    Power,
+    Button(usize, fn(&mut ButtonState)),
+}
+
+fn column_for(name: &str) -> Result<Column, Bk2Error> {
+    let unsupported = || Bk2Error::UnsupportedController(name.to_string());
+    match name {
+        "Reset" => return Ok(Column::Reset),
+        "Power" => return Ok(Column::Power),
+        _ => {}
+    }
+    let port = match name.get(..3) {
+        Some("P1 ") => 0,
+        Some("P2 ") => 1,
+        _ => return Err(unsupported()),
+    };
+    let press: fn(&mut ButtonState) = match &name[3..] {
+        "Up" => |b| b.up = true,
+        "Down" => |b| b.down = true,
+        "Left" => |b| b.left = true,
+        "Right" => |b| b.right = true,
+        "Start" => |b| b.start = true,
+        "Select" => |b| b.select = true,
+        "B" => |b| b.b = true,
+        "A" => |b| b.a = true,
+        _ => return Err(unsupported()),
+    };
+    Ok(Column::Button(port, press))
+}
+
+fn parse_input_log(log: &str) -> Result<Vec<MovieFrame>, Bk2Error> {
+    let mut groups: Vec<Vec<Column>> = Vec::new();
+    let mut frames = Vec::new();
+    for (i, line) in log.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if let Some(key) = line.strip_prefix("LogKey:") {
+            // Groups start with a '#', and contain buttons separated by '|'
+            groups = key
+                .split('#')
+                .filter(|g| !g.is_empty())
+                .map(|g| {
+                    g.split('|')
+                        .filter(|b| !b.is_empty())
+                        .map(column_for)
+                        .collect()
+                })
+                .collect::<Result<_, _>>()?;
+            continue;
+        }
+        if !line.starts_with('|') {
+            continue;
+        }
+        let err = Bk2Error::InvalidInput(i + 1);
+        let fields: Vec<&str> = line[1..].trim_end_matches('|').split('|').collect();
+        if fields.len() != groups.len() {
+            return Err(err);
+        }
+        let mut frame = MovieFrame::default();
+        for (field, columns) in fields.iter().zip(&groups) {
+            if field.len() != columns.len() {
+                return Err(err);
+            }
+            for (c, &column) in field.bytes().zip(columns) {
+                if c == b'.' || c == b' ' {
+                    continue;
+                }
+                match column {
+                    Column::Reset => frame.reset = true,
+                    Column::Power => frame.power = true,
+                    Column::Button(0, press) => press(&mut frame.port1),
+                    Column::Button(_, press) => press(&mut frame.port2),
+                }
+            }
+        }
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    let bytes = data.get(at..at + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// A file inside of a zip archive
+struct Entry<'a> {
+    name: &'a [u8],
+    method: u16,
+    compressed_size: usize,
+    /// The size of the file once decompressed, as declared by the archive
+    size: usize,
+    local_header: usize,
+}
+
+/// Just enough of a zip reader to extract the files in a BK2.
+///
+/// More info: https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+struct Archive<'a> {
+    data: &'a [u8],
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> Archive<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, Bk2Error> {
+        Archive::read_entries(data)
+            .map(|entries| Archive { data, entries })
+            .ok_or(Bk2Error::InvalidArchive)
+    }
+
+    fn read_entries(data: &'a [u8]) -> Option<Vec<Entry<'a>>> {
+        // The end of central directory record is at the end, before a comment
+        let end = (0..data.len().checked_sub(21)?)
+            .rev()
+            .find(|&i| read_u32(data, i) == Some(0x0605_4B50))?;
+        let count = read_u16(data, end + 10)? as usize;
+        let mut at = read_u32(data, end + 16)? as usize;
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            if read_u32(data, at)? != 0x0201_4B50 {
+                return None;
+            }
+            let name_len = read_u16(data, at + 28)? as usize;
+            let extra_len = read_u16(data, at + 30)? as usize;
+            let comment_len = read_u16(data, at + 32)? as usize;
+            entries.push(Entry {
+                name: data.get(at + 46..at + 46 + name_len)?,
+                method: read_u16(data, at + 10)?,
+                compressed_size: read_u32(data, at + 20)? as usize,
+                size: read_u32(data, at + 24)? as usize,
+                local_header: read_u32(data, at + 42)? as usize,
+            });
+            at += 46 + name_len + extra_len + comment_len;
+        }
+        Some(entries)
+    }
+
+    fn find(&self, name: &str) -> Option<&Entry<'a>> {
+        self.entries.iter().find(|e| e.name == name.as_bytes())
+    }
+
+    fn read_text(&self, name: &'static str) -> Result<String, Bk2Error> {
+        let entry = self.find(name).ok_or(Bk2Error::MissingFile(name))?;
+        self.read_text_entry(entry)
+    }
+
+    fn read_text_entry(&self, entry: &Entry) -> Result<String, Bk2Error> {
+        let at = entry.local_header;
+        let start = (|| {
+            if read_u32(self.data, at)? != 0x0403_4B50 {
+                return None;
+            }
+            let name_len = read_u16(self.data, at + 26)? as usize;
+            let extra_len = read_u16(self.data, at + 28)? as usize;
+            Some(at + 30 + name_len + extra_len)
+        })()
+        .ok_or(Bk2Error::InvalidArchive)?;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .ok_or(Bk2Error::InvalidArchive)?;
+        // Files never get bigger than their declared size, so that a crafted
+        // archive can't make us inflate an unbounded amount of data
+        let bytes = match entry.method {
+            0 if compressed.len() == entry.size => compressed.to_vec(),
+            0 => return Err(Bk2Error::InvalidArchive),
+            8 => miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, entry.size)
+                .map_err(|_| Bk2Error::InvalidArchive)?,
+            method => return Err(Bk2Error::UnsupportedCompression(method)),
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        Ok(text.trim_start_matches('\u{FEFF}').to_string())
+    }
+}
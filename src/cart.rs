@@ -1,4 +1,5 @@
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 /// Represents the possible errors when decoding a Cart
 #[derive(Clone, Copy, Debug)]
@@ -62,12 +63,22 @@ impl Mirroring {
 ///
 /// In theory, the mapper id in a cart could be any byte, but only a small subset
 /// of mappers were actually used.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MapperID {
     /// The mapper used for 0x0 and 0x2
     M2,
     /// iNES mapper 0x1
     M1,
+    /// iNES mapper 21, used for VRC4a and VRC4c
+    M21,
+    /// iNES mapper 22, used for VRC2a
+    M22,
+    /// iNES mapper 23, used for VRC4e, VRC4f, and VRC2b
+    M23,
+    /// iNES mapper 25, used for VRC4b, VRC4d, and VRC2c
+    M25,
+    /// iNES mapper 30, used for UNROM 512
+    M30,
 }
 
 impl TryFrom<u8> for MapperID {
@@ -78,6 +89,11 @@ impl TryFrom<u8> for MapperID {
             0 => Ok(MapperID::M2),
             1 => Ok(MapperID::M1),
             2 => Ok(MapperID::M2),
+            21 => Ok(MapperID::M21),
+            22 => Ok(MapperID::M22),
+            23 => Ok(MapperID::M23),
+            25 => Ok(MapperID::M25),
+            30 => Ok(MapperID::M30),
             _ => Err(CartReadingError::UnknownMapper(byte)),
         }
     }
@@ -86,15 +102,25 @@ impl TryFrom<u8> for MapperID {
 /// Represents an NES Cartridge
 /// The PRG and CHR roms vary in sizes between carts,
 /// which is why they're stored in Vecs.
+///
+/// These are shared between a console and its save states, and only get
+/// copied when written to, by flash carts or carts with CHR RAM.
+#[derive(Clone)]
 pub struct Cart {
     /// Represents the PRG ROM, in multiple 16KB chunks
-    pub prg: Vec<u8>,
+    pub prg: Arc<Vec<u8>>,
     /// Represents the CHR ROM, in multiple 8KB chunks
-    pub chr: Vec<u8>,
+    pub chr: Arc<Vec<u8>>,
+    /// Whether `chr` is RAM the game fills in itself, because the cart has no CHR ROM
+    pub chr_ram: bool,
     /// The SRAM, always 8KB
     pub sram: [u8; 0x2000],
     /// The ID of the Mapper this cart uses
     pub mapper: MapperID,
+    /// The NES 2.0 submapper number, 0 if unspecified.
+    ///
+    /// Some mappers use this to distinguish boards wired differently.
+    pub submapper: u8,
     /// What type of mirroring is used in this cart
     pub mirroring: Mirroring,
     /// Indicates whether or not a battery backed RAM is present
@@ -118,15 +144,19 @@ impl Cart {
         let chr_chunks = buffer[5] as usize;
         let flag6 = buffer[6];
         let flag7 = buffer[7];
+        // NES 2.0 headers store the submapper in the upper nibble of byte 8
+        let is_nes2 = flag7 & 0x0C == 0x08;
+        let submapper = if is_nes2 { buffer[8] >> 4 } else { 0 };
         let trainer_offset = if flag6 & 0b100 > 0 { 512 } else { 0 };
         let prg_start = 16 + trainer_offset;
         let prg_end = prg_start + 0x4000 * prg_chunks;
         let chr_end = prg_end + 0x2000 * chr_chunks;
         let mapper = MapperID::try_from((flag6 >> 4) | (flag7 & 0xF0))?;
-        let mirroring = if flag6 & 1 != 0 {
-            Mirroring::Vertical
-        } else {
-            Mirroring::Horizontal
+        let mirroring = match (mapper, flag6 & 0b1001) {
+            // UNROM 512 uses the four screen bit for switchable single screen
+            (MapperID::M30, 0b1000) => Mirroring::SingleLower,
+            (_, m) if m & 1 != 0 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
         };
         let chr = if chr_chunks == 0 {
             vec![0; 0x2000]
@@ -134,9 +164,11 @@ impl Cart {
             buffer[prg_end..chr_end].to_vec()
         };
         Ok(Cart {
-            prg: buffer[prg_start..prg_end].to_vec(),
-            chr,
+            prg: Arc::new(buffer[prg_start..prg_end].to_vec()),
+            chr: Arc::new(chr),
+            chr_ram: chr_chunks == 0,
             mapper,
+            submapper,
             sram: [0; 0x2000],
             mirroring,
             has_battery: flag6 & 0b10 > 0,
@@ -0,0 +1,98 @@
+//! Logging what every byte of the ROM gets used for, as code or as data.
+//!
+//! The log uses the same flags as FCEUX, and can be exported to its
+//! `.cdl` format, for use with disassemblers.
+//!
+//! More info: https://fceux.com/web/help/CodeDataLogger.html
+
+/// The PRG byte was executed as code
+pub const PRG_CODE: u8 = 0x01;
+/// The PRG byte was read as data
+pub const PRG_DATA: u8 = 0x02;
+/// The PRG byte was jumped to indirectly
+pub const PRG_INDIRECT_CODE: u8 = 0x10;
+/// The PRG byte was read as data through a pointer
+pub const PRG_INDIRECT_DATA: u8 = 0x20;
+/// The PRG byte was fetched by the DMC, as audio samples
+pub const PRG_PCM: u8 = 0x40;
+/// The CHR byte was fetched by the PPU while rendering
+pub const CHR_RENDERED: u8 = 0x01;
+/// The CHR byte was read by the CPU through $2007
+pub const CHR_READ: u8 = 0x02;
+
+/// A log of how each byte of PRG and CHR ROM was accessed.
+///
+/// Each byte of the log holds the flags for the byte of ROM at the same offset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeDataLog {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+    /// The flags CPU reads are currently logged with
+    pub(crate) access: u8,
+}
+
+impl CodeDataLog {
+    /// Creates an empty log, for ROMs of a given size.
+    ///
+    /// Carts using CHR RAM should have a CHR size of 0.
+    pub fn new(prg_size: usize, chr_size: usize) -> Self {
+        CodeDataLog {
+            prg: vec![0; prg_size],
+            chr: vec![0; chr_size],
+            access: PRG_DATA,
+        }
+    }
+
+    /// Returns the flags logged for each byte of PRG ROM.
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    /// Returns the flags logged for each byte of CHR ROM.
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    /// Clears every flag, keeping the sizes.
+    pub fn clear(&mut self) {
+        for byte in self.prg.iter_mut().chain(self.chr.iter_mut()) {
+            *byte = 0;
+        }
+    }
+
+    /// Returns how many bytes of PRG were logged as code, and as data.
+    pub fn prg_coverage(&self) -> (usize, usize) {
+        let count = |flag| self.prg.iter().filter(|&&b| b & flag != 0).count();
+        (count(PRG_CODE), count(PRG_DATA))
+    }
+
+    /// Exports the log in FCEUX's `.cdl` format.
+    ///
+    /// This is just the flags for PRG, followed by the flags for CHR.
+    pub fn to_cdl(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(self.prg.len() + self.chr.len());
+        res.extend_from_slice(&self.prg);
+        res.extend_from_slice(&self.chr);
+        res
+    }
+
+    /// Logs a read of PRG ROM, from a CPU address, with some flags.
+    pub(crate) fn log_prg(&mut self, offset: usize, address: u16, flags: u8) {
+        // Code and data also remember which 8KB window they were read through
+        let window = if flags & (PRG_CODE | PRG_DATA) != 0 {
+            ((address & 0x6000) >> 11) as u8
+        } else {
+            0
+        };
+        if let Some(byte) = self.prg.get_mut(offset) {
+            *byte |= flags | window;
+        }
+    }
+
+    /// Logs an access to CHR ROM.
+    pub(crate) fn log_chr(&mut self, offset: usize, flags: u8) {
+        if let Some(byte) = self.chr.get_mut(offset) {
+            *byte |= flags;
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Cheat codes, either patching cartridge reads, or freezing memory.
+//!
+//! Game Genie codes replace the values the CPU reads from the cartridge,
+//! while Pro Action Replay codes write a value to RAM every frame.
+//!
+//! More info: https://wiki.nesdev.com/w/index.php/Game_Genie
+
+/// Represents the possible errors when decoding a cheat code
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheatError {
+    /// The code isn't in any format we recognise
+    UnrecognisedFormat(String),
+    /// The code contains a character that isn't a Game Genie letter
+    InvalidLetter(char),
+}
+
+/// The letters used by the Game Genie, in order of their value
+const GENIE_LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+/// Represents a single decoded cheat code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cheat {
+    /// Replaces reads of a cartridge address, if the original matches `compare`
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes a value to an address of RAM or SRAM every frame
+    Freeze { address: u16, value: u8 },
+}
+
+impl Cheat {
+    /// Decodes a cheat code, detecting its format.
+    ///
+    /// This accepts 6 and 8 letter Game Genie codes, 8 digit Pro Action Replay
+    /// codes, and raw codes written as `address:value`, in hexadecimal.
+    /// Since A and E are both Game Genie letters and hex digits, 8 letter
+    /// codes made only of them are read as Game Genie codes.
+    pub fn decode(code: &str) -> Result<Cheat, CheatError> {
+        let code = code.trim();
+        let unrecognised = || CheatError::UnrecognisedFormat(code.to_string());
+        if let Some(colon) = code.find(':') {
+            let address = u16::from_str_radix(&code[..colon], 16).map_err(|_| unrecognised())?;
+            let value = u8::from_str_radix(&code[colon + 1..], 16).map_err(|_| unrecognised())?;
+            return Ok(Cheat::Freeze { address, value });
+        }
+        let is_hex = code.chars().all(|c| c.is_ascii_hexdigit());
+        let is_genie = code
+            .chars()
+            .all(|c| c.is_ascii() && GENIE_LETTERS.contains(&(c.to_ascii_uppercase() as u8)));
+        match code.len() {
+            // Pro Action Replay codes are 4 bytes, ignoring the first one
+            8 if is_hex && !is_genie => {
+                let raw = u32::from_str_radix(code, 16).map_err(|_| unrecognised())?;
+                let [_, hi, lo, value] = raw.to_be_bytes();
+                Ok(Cheat::Freeze {
+                    address: u16::from_be_bytes([hi, lo]),
+                    value,
+                })
+            }
+            6 | 8 => Cheat::decode_genie(code),
+            _ => Err(unrecognised()),
+        }
+    }
+
+    fn decode_genie(code: &str) -> Result<Cheat, CheatError> {
+        let mut n = [0u16; 8];
+        for (i, c) in code.chars().enumerate() {
+            let upper = c.to_ascii_uppercase() as u8;
+            let position = GENIE_LETTERS.iter().position(|&l| l == upper);
+            n[i] = position.ok_or(CheatError::InvalidLetter(c))? as u16;
+        }
+        // The bits of each field are scattered across the letters
+        let address = 0x8000
+            | ((n[3] & 7) << 12)
+            | ((n[5] & 7) << 8)
+            | ((n[4] & 8) << 8)
+            | ((n[2] & 7) << 4)
+            | ((n[1] & 8) << 4)
+            | (n[4] & 7)
+            | (n[3] & 8);
+        let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+        if code.len() == 6 {
+            Ok(Cheat::GameGenie {
+                address,
+                value: (value | (n[5] & 8)) as u8,
+                compare: None,
+            })
+        } else {
+            let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+            Ok(Cheat::GameGenie {
+                address,
+                value: (value | (n[7] & 8)) as u8,
+                compare: Some(compare as u8),
+            })
+        }
+    }
+}
+
+/// A list of cheats, each of which can be turned on and off.
+#[derive(Clone, Debug, Default)]
+pub struct Cheats {
+    cheats: Vec<(Cheat, bool)>,
+    /// The enabled Game Genie codes, as (address, value, compare)
+    patches: Vec<(u16, u8, Option<u8>)>,
+    /// The enabled freeze codes, as (address, value)
+    freezes: Vec<(u16, u8)>,
+}
+
+impl Cheats {
+    /// Decodes a cheat code, and adds it, enabled.
+    ///
+    /// This returns the index of the cheat in the list.
+    pub fn add(&mut self, code: &str) -> Result<usize, CheatError> {
+        let cheat = Cheat::decode(code)?;
+        self.cheats.push((cheat, true));
+        self.update();
+        Ok(self.cheats.len() - 1)
+    }
+
+    /// Turns a cheat on or off. This does nothing if the cheat doesn't exist.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.1 = enabled;
+            self.update();
+        }
+    }
+
+    /// Removes a cheat, shifting the cheats after it down by one.
+    pub fn remove(&mut self, index: usize) {
+        if index < self.cheats.len() {
+            self.cheats.remove(index);
+            self.update();
+        }
+    }
+
+    /// Removes every cheat.
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+        self.update();
+    }
+
+    /// Returns every cheat, along with whether or not it's enabled.
+    pub fn list(&self) -> &[(Cheat, bool)] {
+        &self.cheats
+    }
+
+    fn update(&mut self) {
+        self.patches.clear();
+        self.freezes.clear();
+        for &(cheat, enabled) in &self.cheats {
+            match cheat {
+                _ if !enabled => {}
+                Cheat::GameGenie {
+                    address,
+                    value,
+                    compare,
+                } => self.patches.push((address, value, compare)),
+                Cheat::Freeze { address, value } => self.freezes.push((address, value)),
+            }
+        }
+    }
+
+    /// Applies Game Genie codes to a value read from the cartridge.
+    pub(crate) fn patch(&self, address: u16, read: u8) -> u8 {
+        for &(a, value, compare) in &self.patches {
+            if a == address && compare.is_none_or(|c| c == read) {
+                return value;
+            }
+        }
+        read
+    }
+
+    /// Returns true if some Game Genie codes are enabled.
+    pub(crate) fn has_patches(&self) -> bool {
+        !self.patches.is_empty()
+    }
+
+    /// Returns the enabled freeze codes, as (address, value).
+    pub(crate) fn freezes(&self) -> &[(u16, u8)] {
+        &self.freezes
+    }
+}
@@ -1,14 +1,33 @@
 use crate::apu::APU;
 use crate::cart::Cart;
-use crate::controller::ButtonState;
+use crate::cdl::CodeDataLog;
+use crate::cheats::Cheats;
+use crate::controller::{ButtonState, Controller};
 use crate::cpu::CPU;
+use crate::events::EventLog;
+use crate::hooks::{Hooks, Registers};
+use crate::input::{InputDevice, Unplugged};
 use crate::memory::MemoryBus;
-use crate::ports::{AudioDevice, VideoDevice};
+use crate::multitap::{Multitap, MultitapMode};
+use crate::palette::Palette;
+use crate::ports::{AudioDevice, Image, Layers, PixelBuffer, VideoDevice};
 use crate::ppu::PPU;
+use crate::vaus::{Vaus, VausWiring};
+use crate::zapper::Zapper;
+
+/// A snapshot of the entire state of a console.
+///
+/// This can be loaded back into a console with `Console::load_state`.
+/// This includes the cartridge, so it can be used across consoles
+/// playing the same game. The ROM is shared with the console rather than
+/// copied, and things attached by the host, like hooks and logs, are left out.
+#[derive(Clone)]
+pub struct SaveState(Console);
 
 /// Used to act as an owner of everything needed to run a game
 /// Is also responsible for holding ram,
 /// as well as communication between processors.
+#[derive(Clone)]
 pub struct Console {
     apu: APU,
     cpu: CPU,
@@ -39,10 +58,13 @@ impl Console {
         let cpucycles = self.cpu.step();
         let m = &mut self.cpu.mem;
         for _ in 0..cpucycles * 3 {
-            self.ppu.step(m, video);
+            if self.ppu.step(m, video) {
+                m.end_frame();
+            }
         }
         for _ in 0..cpucycles {
             self.apu.step(m, audio);
+            m.step_mapper();
         }
         cpucycles
     }
@@ -77,16 +99,436 @@ impl Console {
             let cpucycles = self.cpu.step();
             let m = &mut self.cpu.mem;
             for _ in 0..cpucycles * 3 {
-                frame_happened = self.ppu.step(m, video) || frame_happened;
+                if self.ppu.step(m, video) {
+                    m.end_frame();
+                    frame_happened = true;
+                }
             }
             for _ in 0..cpucycles {
                 self.apu.step(m, audio);
+                m.step_mapper();
             }
         }
     }
 
+    /// Updates the buttons held on the first controller.
+    ///
+    /// This does nothing if a standard controller isn't plugged into the first port.
     pub fn update_controller(&mut self, buttons: ButtonState) {
-        self.cpu.set_buttons(buttons);
+        if let Some(controller) = self.port1_device::<Controller>() {
+            controller.set_buttons(buttons);
+        }
+    }
+
+    /// Updates the buttons held on the second controller.
+    ///
+    /// This does nothing if a standard controller isn't plugged into the second port.
+    pub fn update_controller2(&mut self, buttons: ButtonState) {
+        if let Some(controller) = self.port2_device::<Controller>() {
+            controller.set_buttons(buttons);
+        }
+    }
+
+    /// Plugs a device into the first port, replacing the current one.
+    pub fn plug_port1<D: InputDevice + 'static>(&mut self, device: D) {
+        self.cpu.mem.port1 = Box::new(device);
+        self.cpu.mem.update_light_sensing();
+    }
+
+    /// Plugs a device into the second port, replacing the current one.
+    pub fn plug_port2<D: InputDevice + 'static>(&mut self, device: D) {
+        self.cpu.mem.port2 = Box::new(device);
+        self.cpu.mem.update_light_sensing();
+    }
+
+    /// Plugs a device into the Famicom expansion port, replacing the current ones.
+    ///
+    /// Expansion devices are split in two, with the first part being read
+    /// through $4016, and the second through $4017. The bits they return
+    /// are combined with those of the device in the matching port.
+    pub fn plug_expansion<D1, D2>(&mut self, first: D1, second: D2)
+    where
+        D1: InputDevice + 'static,
+        D2: InputDevice + 'static,
+    {
+        self.cpu.mem.expansion1 = Box::new(first);
+        self.cpu.mem.expansion2 = Box::new(second);
+        self.cpu.mem.update_light_sensing();
+    }
+
+    /// Returns the device plugged into the first port, if it has this type.
+    pub fn port1_device<D: InputDevice + 'static>(&mut self) -> Option<&mut D> {
+        self.cpu.mem.port1.as_any_mut().downcast_mut()
+    }
+
+    /// Returns the device plugged into the second port, if it has this type.
+    pub fn port2_device<D: InputDevice + 'static>(&mut self) -> Option<&mut D> {
+        self.cpu.mem.port2.as_any_mut().downcast_mut()
+    }
+
+    /// Plugs a four player adapter into both ports, or plugs controllers back in.
+    pub fn set_multitap(&mut self, mode: Option<MultitapMode>) {
+        match mode {
+            Some(mode) => {
+                self.plug_port1(Multitap::new(mode, 0));
+                self.plug_port2(Multitap::new(mode, 1));
+            }
+            None => {
+                self.plug_port1(Controller::new());
+                self.plug_port2(Controller::new());
+            }
+        }
+    }
+
+    /// Updates the buttons held by all 4 players using a four player adapter.
+    ///
+    /// This does nothing if no adapter is plugged in.
+    pub fn update_multitap(&mut self, players: [ButtonState; 4]) {
+        if let Some(multitap) = self.port1_device::<Multitap>() {
+            multitap.set_buttons(players[0], players[2]);
+        }
+        if let Some(multitap) = self.port2_device::<Multitap>() {
+            multitap.set_buttons(players[1], players[3]);
+        }
+    }
+
+    /// Returns the first part of the expansion device, if it has this type.
+    pub fn expansion1_device<D: InputDevice + 'static>(&mut self) -> Option<&mut D> {
+        self.cpu.mem.expansion1.as_any_mut().downcast_mut()
+    }
+
+    /// Returns the second part of the expansion device, if it has this type.
+    pub fn expansion2_device<D: InputDevice + 'static>(&mut self) -> Option<&mut D> {
+        self.cpu.mem.expansion2.as_any_mut().downcast_mut()
+    }
+
+    /// Plugs in a Vaus controller, with the given wiring, or unplugs it.
+    ///
+    /// The NES version replaces the second controller, while the Famicom
+    /// version uses the expansion port, keeping both controllers.
+    pub fn set_vaus(&mut self, wiring: Option<VausWiring>) {
+        if self.port2_device::<Vaus>().is_some() {
+            self.plug_port2(Controller::new());
+        }
+        if self.expansion1_device::<Vaus>().is_some() {
+            self.plug_expansion(Unplugged, Unplugged);
+        }
+        match wiring {
+            Some(VausWiring::Nes) => self.plug_port2(Vaus::new(VausWiring::Nes, 1)),
+            Some(VausWiring::Famicom) => self.plug_expansion(
+                Vaus::new(VausWiring::Famicom, 0),
+                Vaus::new(VausWiring::Famicom, 1),
+            ),
+            None => {}
+        }
+    }
+
+    /// Updates the knob position of the Vaus, from 0.0 to 1.0, and its fire button.
+    ///
+    /// This does nothing if no Vaus is plugged in.
+    pub fn update_vaus(&mut self, position: f32, fire: bool) {
+        let mem = &mut self.cpu.mem;
+        for device in &mut [&mut mem.port2, &mut mem.expansion1, &mut mem.expansion2] {
+            if let Some(vaus) = device.as_any_mut().downcast_mut::<Vaus>() {
+                vaus.set_analog(position);
+                vaus.set_fire(fire);
+            }
+        }
+    }
+
+    /// Plugs a Zapper into the second port, or plugs a controller back in.
+    ///
+    /// Games like Duck Hunt expect a Zapper instead of a controller.
+    pub fn set_zapper_connected(&mut self, connected: bool) {
+        let plugged = self.port2_device::<Zapper>().is_some();
+        if connected && !plugged {
+            self.plug_port2(Zapper::new());
+        } else if !connected && plugged {
+            self.plug_port2(Controller::new());
+        }
+    }
+
+    /// Aims the Zapper at a given pixel on the screen.
+    ///
+    /// Coordinates outside of the screen mean that the Zapper is
+    /// pointed away from it, and will never see any light.
+    pub fn aim_zapper(&mut self, x: i32, y: i32) {
+        if let Some(zapper) = self.port2_device::<Zapper>() {
+            zapper.aim(x, y);
+        }
+    }
+
+    /// Pulls or releases the trigger of the Zapper.
+    pub fn set_zapper_trigger(&mut self, pulled: bool) {
+        if let Some(zapper) = self.port2_device::<Zapper>() {
+            zapper.set_trigger(pulled);
+        }
+    }
+
+    /// Returns true if the game didn't read the controllers during the last frame.
+    ///
+    /// Input given during a lag frame is ignored by the game, so this is
+    /// useful to know if an input actually had any effect.
+    pub fn last_frame_was_lag(&self) -> bool {
+        self.cpu.mem.polling.last_reads == 0
+    }
+
+    /// Returns how many lag frames have happened since the console was created.
+    pub fn lag_frames(&self) -> u64 {
+        self.cpu.mem.polling.lag_frames
+    }
+
+    /// Sets the lag frame counter back to 0.
+    pub fn reset_lag_frames(&mut self) {
+        self.cpu.mem.polling.lag_frames = 0;
+    }
+
+    /// Returns how many times the controllers were read during the last frame.
+    pub fn last_frame_polls(&self) -> u32 {
+        self.cpu.mem.polling.last_reads
+    }
+
+    /// Returns how many times the controllers were strobed during the last frame.
+    ///
+    /// Games strobe the controllers to latch the buttons before reading them.
+    pub fn last_frame_strobes(&self) -> u32 {
+        self.cpu.mem.polling.last_strobes
+    }
+
+    /// Returns the cheats applied to this console.
+    pub fn cheats(&self) -> &Cheats {
+        &self.cpu.mem.cheats
+    }
+
+    /// Returns the cheats applied to this console, allowing them to be changed.
+    ///
+    /// Cheats are part of save states, so loading a state restores
+    /// the cheats that were active when it was saved.
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cpu.mem.cheats
+    }
+
+    /// Returns which layers get drawn to the pixel buffer.
+    pub fn layers(&self) -> &Layers {
+        &self.ppu.layers
+    }
+
+    /// Returns which layers get drawn, allowing layers to be hidden.
+    ///
+    /// Hiding layers only changes the frames drawn, not what the game sees.
+    /// Layers aren't part of save states, and are kept when loading one.
+    pub fn layers_mut(&mut self) -> &mut Layers {
+        &mut self.ppu.layers
+    }
+
+    /// Checks if only 8 sprites get drawn on each scanline, like the hardware.
+    pub fn sprite_limit(&self) -> bool {
+        self.ppu.sprite_limit
+    }
+
+    /// Sets whether only 8 sprites get drawn on each scanline.
+    ///
+    /// Turning the limit off draws up to 64 sprites per scanline, which gets rid
+    /// of the flicker games use to show more sprites. The game still sees sprite
+    /// overflow and sprite 0 hits as if the limit was there.
+    /// This setting isn't part of save states, and is kept when loading one.
+    pub fn set_sprite_limit(&mut self, limit: bool) {
+        self.ppu.sprite_limit = limit;
+    }
+
+    /// Returns the colors used to draw frames.
+    pub fn palette(&self) -> &Palette {
+        &self.ppu.palette
+    }
+
+    /// Changes the colors used to draw frames, and the debug views of the PPU.
+    ///
+    /// The palette isn't part of save states, and is kept when loading one.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.ppu.palette = palette;
+    }
+
+    /// Returns the hooks registered on this console, allowing new ones to be added.
+    ///
+    /// Hooks aren't part of save states, and are kept when loading one.
+    pub fn hooks_mut(&mut self) -> &mut Hooks {
+        self.cpu.mem.hooks.0.get_or_insert_with(Box::default)
+    }
+
+    /// Removes every hook, so that memory accesses are no longer checked.
+    pub fn clear_hooks(&mut self) {
+        self.cpu.mem.hooks.0 = None;
+    }
+
+    /// Draws both pattern tables side by side, using one of the 8 palettes.
+    ///
+    /// Palettes 0 to 3 are for the background, and 4 to 7 for sprites.
+    pub fn pattern_tables_image(&self, palette: u8) -> Image {
+        let m = &self.cpu.mem;
+        m.ppu.pattern_tables_image(&*m.mapper, &self.ppu.palette, palette)
+    }
+
+    /// Draws the four nametables, optionally outlining the part on screen.
+    pub fn nametables_image(&self, scroll_overlay: bool) -> Image {
+        let m = &self.cpu.mem;
+        m.ppu.nametables_image(&*m.mapper, &self.ppu.palette, scroll_overlay)
+    }
+
+    /// Draws every sprite in OAM, in an 8 by 8 grid.
+    pub fn sprites_image(&self) -> Image {
+        let m = &self.cpu.mem;
+        m.ppu.sprites_image(&*m.mapper, &self.ppu.palette)
+    }
+
+    /// Draws the 32 colors of palette RAM.
+    pub fn palette_image(&self) -> Image {
+        self.cpu.mem.ppu.palette_image(&self.ppu.palette)
+    }
+
+    /// Returns the 256 bytes of OAM, holding 4 bytes for each sprite.
+    pub fn oam(&self) -> &[u8; 256] {
+        &self.cpu.mem.ppu.oam.0
+    }
+
+    /// Returns the 32 bytes of palette RAM.
+    pub fn palette_ram(&self) -> [u8; 32] {
+        self.cpu.mem.ppu.palette_ram()
+    }
+
+    /// Returns the registers of the CPU.
+    pub fn registers(&self) -> Registers {
+        self.cpu.registers()
+    }
+
+    /// Changes the registers of the CPU.
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.cpu.set_registers(registers);
+    }
+
+    /// Writes to work RAM or cartridge RAM, ignoring other addresses.
+    ///
+    /// Like `peek`, this has no side effects, and doesn't trigger hooks.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.cpu.mem.poke(address, value);
+    }
+
+    /// Writes to cartridge space through the mapper, ignoring lower addresses.
+    ///
+    /// Unlike `poke`, this reaches the registers of the mapper at $8000 and
+    /// above, so it can switch banks, or program flash, like the game would.
+    /// This still doesn't trigger hooks.
+    pub fn write_cart(&mut self, address: u16, value: u8) {
+        if address >= 0x6000 {
+            self.cpu.mem.mapper.write(address, value);
+        }
+    }
+
+    /// Returns the offset into PRG ROM that a CPU address is currently mapped to.
+    ///
+    /// This is None for addresses outside of ROM, or if the mapper doesn't know.
+    pub fn prg_offset(&self, address: u16) -> Option<usize> {
+        if address >= 0x8000 {
+            self.cpu.mem.mapper.prg_offset(address)
+        } else {
+            None
+        }
+    }
+
+    /// Starts logging how each byte of the ROM is used, with an empty log.
+    ///
+    /// The log keeps going across save states, so that loading a state
+    /// doesn't lose what was logged since it was taken.
+    pub fn start_code_data_log(&mut self) {
+        self.cpu.mem.cdl.0 = Some(Box::new(self.cpu.mem.new_cdl()));
+    }
+
+    /// Returns the code data log, if logging has been started.
+    pub fn code_data_log(&self) -> Option<&CodeDataLog> {
+        self.cpu.mem.cdl.0.as_deref()
+    }
+
+    /// Stops logging, returning what was logged.
+    pub fn stop_code_data_log(&mut self) -> Option<CodeDataLog> {
+        self.cpu.mem.cdl.0.take().map(|cdl| *cdl)
+    }
+
+    /// Starts recording PPU and mapper writes, sprite 0 hits, NMIs and IRQs.
+    ///
+    /// The first frame returned by the log is only partial, since it
+    /// covers whatever was left of the frame logging started in.
+    pub fn start_event_log(&mut self) {
+        self.cpu.mem.events.0 = Some(Box::new(EventLog::new()));
+    }
+
+    /// Returns the event log, if logging has been started.
+    pub fn event_log(&self) -> Option<&EventLog> {
+        self.cpu.mem.events.0.as_deref()
+    }
+
+    /// Stops recording events, returning the log.
+    pub fn stop_event_log(&mut self) -> Option<EventLog> {
+        self.cpu.mem.events.0.take().map(|events| *events)
+    }
+
+    /// Returns the 2KB of work RAM inside the console.
+    ///
+    /// This is useful to inspect the state of a game, since most of
+    /// it will be stored in RAM.
+    pub fn ram(&self) -> &[u8] {
+        self.cpu.mem.ram()
+    }
+
+    /// Returns the 8KB of RAM on the cartridge, at $6000 through $7FFF.
+    ///
+    /// Carts with a battery keep their saves in this memory.
+    pub fn sram(&self) -> Vec<u8> {
+        self.cpu.mem.sram()
+    }
+
+    /// Reads a byte from the CPU's address space, without side effects.
+    ///
+    /// This can see RAM and cartridge memory, but IO registers
+    /// always read as 0, since reading them would change their state.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.cpu.mem.peek(address)
+    }
+
+    /// Returns the PRG data of carts that can rewrite their own PRG.
+    ///
+    /// Carts with flash memory, like UNROM 512, store their saves in PRG,
+    /// so this data should be written back to the rom file for
+    /// saves to persist. This returns None for other carts.
+    pub fn flash_data(&self) -> Option<&[u8]> {
+        self.cpu.mem.mapper.flash()
+    }
+
+    /// Returns the pixels of the frame currently being drawn.
+    ///
+    /// After `step_frame`, this contains the frame that was just finished.
+    pub fn pixels(&self) -> &PixelBuffer {
+        self.ppu.pixels()
+    }
+
+    /// Takes a snapshot of the current state of the console.
+    pub fn save_state(&self) -> SaveState {
+        SaveState(self.clone())
+    }
+
+    /// Restores the console to a previously saved state.
+    pub fn load_state(&mut self, state: &SaveState) {
+        let hooks = self.cpu.mem.hooks.0.take();
+        let cdl = self.cpu.mem.cdl.0.take();
+        let events = self.cpu.mem.events.0.take();
+        let layers = self.ppu.layers;
+        let sprite_limit = self.ppu.sprite_limit;
+        let palette = std::mem::take(&mut self.ppu.palette);
+        *self = state.0.clone();
+        self.ppu.palette = palette;
+        self.ppu.layers = layers;
+        self.ppu.sprite_limit = sprite_limit;
+        self.cpu.mem.hooks.0 = hooks;
+        self.cpu.mem.cdl.0 = cdl;
+        self.cpu.mem.events.0 = events;
     }
 
     /// Resets everything to it's initial state
@@ -1,4 +1,7 @@
-#[derive(Default)]
+use crate::input::InputDevice;
+
+/// Represents which buttons are held down on a controller
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct ButtonState {
     pub a: bool,
     pub b: bool,
@@ -10,15 +13,131 @@ pub struct ButtonState {
     pub right: bool,
 }
 
-/// Represents a controller
-#[derive(Default)]
-pub(crate) struct Controller {
+impl ButtonState {
+    /// Returns the buttons in the order controllers report them in:
+    /// A, B, Select, Start, Up, Down, Left, Right
+    pub(crate) fn bits(self) -> [bool; 8] {
+        [
+            self.a,
+            self.b,
+            self.select,
+            self.start,
+            self.up,
+            self.down,
+            self.left,
+            self.right,
+        ]
+    }
+
+    pub(crate) fn from_bits(bits: [bool; 8]) -> Self {
+        let [a, b, select, start, up, down, left, right] = bits;
+        ButtonState {
+            a,
+            b,
+            select,
+            start,
+            up,
+            down,
+            left,
+            right,
+        }
+    }
+}
+
+/// What turbo buttons count, to decide when to toggle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TurboClock {
+    /// Count frames, which gives the same rate no matter how the game reads input
+    Frames,
+    /// Count the times the game strobes the controller, like some turbo
+    /// controllers do, which makes the rate depend on how often the game polls
+    Strobes,
+}
+
+/// Describes which buttons get pressed automatically, and how quickly.
+///
+/// Turbo buttons are pressed for `rate` ticks, and then released for `rate`
+/// ticks, as long as they're held down. Since the ticks are frames or strobes,
+/// and not real time, turbo is deterministic, and can be recorded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Turbo {
+    /// The buttons with turbo enabled
+    pub buttons: ButtonState,
+    /// How many ticks each button spends pressed, and then released
+    pub rate: u32,
+    pub clock: TurboClock,
+}
+
+impl Turbo {
+    /// Enables turbo for A and B, toggling every `rate` frames.
+    pub fn new(rate: u32) -> Self {
+        Turbo {
+            buttons: ButtonState {
+                a: true,
+                b: true,
+                ..ButtonState::default()
+            },
+            rate,
+            clock: TurboClock::Frames,
+        }
+    }
+}
+
+/// A sequence of inputs, each held for a number of frames.
+///
+/// The buttons of a macro are pressed in addition to the buttons
+/// held on the controller.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputMacro {
+    steps: Vec<(ButtonState, u32)>,
+}
+
+impl InputMacro {
+    pub fn new() -> Self {
+        InputMacro::default()
+    }
+
+    /// Adds a step to the macro, holding some buttons for a number of frames.
+    pub fn hold(mut self, buttons: ButtonState, frames: u32) -> Self {
+        if frames > 0 {
+            self.steps.push((buttons, frames));
+        }
+        self
+    }
+
+    /// Adds a step to the macro, where no buttons are pressed.
+    pub fn wait(self, frames: u32) -> Self {
+        self.hold(ButtonState::default(), frames)
+    }
+
+    /// Returns how many frames this macro lasts.
+    pub fn frames(&self) -> u32 {
+        self.steps.iter().map(|&(_, frames)| frames).sum()
+    }
+}
+
+/// Keeps track of where we are in a macro
+#[derive(Clone, Debug)]
+struct MacroPlayback {
+    steps: InputMacro,
+    step: usize,
+    /// How many frames are left in the current step
+    frames_left: u32,
+}
+
+/// Represents a standard controller
+#[derive(Clone, Debug, Default)]
+pub struct Controller {
     /// A bitfield of the buttons, in the following order:
     /// A, B, Select, Start, Up, Down, Left, Right
     buttons: [bool; 8],
     /// What button is currently being read
     index: u8,
     strobe: bool,
+    turbo: Option<Turbo>,
+    /// How many frames or strobes have happened since turbo was enabled
+    turbo_ticks: u32,
+    playback: Option<MacroPlayback>,
 }
 
 impl Controller {
@@ -27,36 +146,100 @@ impl Controller {
     }
 
     pub fn set_buttons(&mut self, buttons: ButtonState) {
-        self.buttons = [
-            buttons.a,
-            buttons.b,
-            buttons.select,
-            buttons.start,
-            buttons.up,
-            buttons.down,
-            buttons.left,
-            buttons.right,
-        ];
-    }
-
-    pub fn read(&mut self) -> u8 {
+        self.buttons = buttons.bits();
+    }
+
+    /// Returns the buttons currently held on this controller
+    pub fn buttons(&self) -> ButtonState {
+        ButtonState::from_bits(self.buttons)
+    }
+
+    /// Enables turbo for some buttons, or disables it.
+    pub fn set_turbo(&mut self, turbo: Option<Turbo>) {
+        self.turbo = turbo;
+        self.turbo_ticks = 0;
+    }
+
+    /// Starts playing a macro, replacing any macro already playing.
+    pub fn play_macro(&mut self, steps: InputMacro) {
+        self.playback = if steps.steps.is_empty() {
+            None
+        } else {
+            let frames_left = steps.steps[0].1;
+            Some(MacroPlayback {
+                steps,
+                step: 0,
+                frames_left,
+            })
+        };
+    }
+
+    /// Returns true if a macro is still being played.
+    pub fn playing_macro(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Returns the buttons the game sees, after applying macros and turbo
+    pub fn pressed(&self) -> ButtonState {
+        let mut bits = self.buttons;
+        if let Some(playback) = &self.playback {
+            let (buttons, _) = playback.steps.steps[playback.step];
+            for (bit, held) in bits.iter_mut().zip(buttons.bits().iter()) {
+                *bit |= held;
+            }
+        }
+        if let Some(turbo) = &self.turbo {
+            let released = (self.turbo_ticks / turbo.rate.max(1)) % 2 == 1;
+            if released {
+                for (bit, turbo) in bits.iter_mut().zip(turbo.buttons.bits().iter()) {
+                    *bit &= !turbo;
+                }
+            }
+        }
+        ButtonState::from_bits(bits)
+    }
+}
+
+impl InputDevice for Controller {
+    fn read(&mut self) -> u8 {
         let index = self.index as usize;
-        let res = if *self.buttons.get(index).unwrap_or(&false) {
+        let buttons = self.pressed().bits();
+        let res = if *buttons.get(index).unwrap_or(&false) {
             1
         } else {
             0
         };
-        self.index += 1;
+        self.index = self.index.saturating_add(1);
         if self.strobe {
             self.index = 0;
         }
         res
     }
 
-    pub fn write(&mut self, value: u8) {
-        self.strobe = value & 1 == 1;
+    fn write(&mut self, value: u8) {
+        let strobe = value & 1 == 1;
+        if strobe && !self.strobe && self.turbo.map(|t| t.clock) == Some(TurboClock::Strobes) {
+            self.turbo_ticks = self.turbo_ticks.wrapping_add(1);
+        }
+        self.strobe = strobe;
         if self.strobe {
             self.index = 0;
         }
     }
+
+    fn end_frame(&mut self) {
+        if self.turbo.map(|t| t.clock) == Some(TurboClock::Frames) {
+            self.turbo_ticks = self.turbo_ticks.wrapping_add(1);
+        }
+        if let Some(playback) = &mut self.playback {
+            playback.frames_left -= 1;
+            if playback.frames_left == 0 {
+                playback.step += 1;
+                match playback.steps.steps.get(playback.step) {
+                    Some(&(_, frames)) => playback.frames_left = frames,
+                    None => self.playback = None,
+                }
+            }
+        }
+    }
 }
@@ -1,5 +1,6 @@
+use super::cdl::{PRG_CODE, PRG_DATA, PRG_INDIRECT_CODE, PRG_INDIRECT_DATA};
+use super::hooks::Registers;
 use super::memory::MemoryBus;
-use crate::controller::ButtonState;
 
 // The various addressing modes of each opcode
 const OP_MODES: [u8; 256] = [
@@ -111,7 +112,7 @@ fn branch_cycles(pc: u16, address: u16) -> i32 {
 }
 
 /// Represents public CPU state
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct CPUState {
     /// Represents the interrupt, None representing no interrupt
     interrupt: Option<Interrupt>,
@@ -128,8 +129,14 @@ impl CPUState {
         self.interrupt = Some(Interrupt::NMI);
     }
 
+    /// Requests an IRQ, unless an NMI is already pending.
+    ///
+    /// IRQs are level triggered, so a device holding the line low
+    /// can call this on every cycle until it gets acknowledged.
     pub fn set_irq(&mut self) {
-        self.interrupt = Some(Interrupt::IRQ);
+        if self.interrupt.is_none() {
+            self.interrupt = Some(Interrupt::IRQ);
+        }
     }
 
     pub fn clear_interrupt(&mut self) {
@@ -143,6 +150,7 @@ impl CPUState {
 
 /// Represents possible CPU interrupts
 /// Represents the CPU
+#[derive(Clone)]
 pub(crate) struct CPU {
     /// Program counter
     pc: u16,
@@ -206,11 +214,6 @@ impl CPU {
         self.set_flags(0x24);
     }
 
-    /// Sets the buttons for controller 1
-    pub fn set_buttons(&mut self, buttons: ButtonState) {
-        self.mem.controller1.set_buttons(buttons);
-    }
-
     fn set_flags(&mut self, flags: u8) {
         self.c = flags & 1;
         self.z = (flags >> 1) & 1;
@@ -235,6 +238,33 @@ impl CPU {
         r
     }
 
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            sp: self.sp,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            p: self.get_flags(),
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.pc = registers.pc;
+        self.sp = registers.sp;
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.set_flags(registers.p);
+    }
+
+    /// Lets execution hooks see and change the registers before an instruction.
+    fn run_exec_hooks(&mut self) {
+        let mut registers = self.registers();
+        self.mem.with_hooks((), |hooks, ctx| hooks.exec(ctx, &mut registers));
+        self.set_registers(registers);
+    }
+
     pub fn read(&mut self, address: u16) -> u8 {
         self.mem.cpu_read(address)
     }
@@ -347,17 +377,27 @@ impl CPU {
             i
         };
         match interrupt {
-            None => {}
             Some(Interrupt::NMI) => {
                 self.nmi();
                 cycles += 7;
             }
-            Some(Interrupt::IRQ) => {
+            // IRQs are masked by the interrupt disable flag
+            Some(Interrupt::IRQ) if self.i == 0 => {
                 self.irq();
                 cycles += 7;
             }
+            _ => {}
         }
 
+        if self.mem.hooks.0.as_ref().is_some_and(|h| h.has_execs()) {
+            self.run_exec_hooks();
+        }
+
+        // The instruction and its operands are code, and what it reads is data
+        let logging = self.mem.cdl.0.is_some();
+        if logging {
+            self.mem.set_cdl_access(PRG_CODE);
+        }
         let opcode = {
             let pc = self.pc;
             self.read(pc)
@@ -397,7 +437,10 @@ impl CPU {
             Addressing::Indirect => {
                 let next = self.pc.wrapping_add(1);
                 let read = self.read16(next);
-                self.read16bug(read)
+                self.mem.set_cdl_access(PRG_DATA);
+                let address = self.read16bug(read);
+                self.mem.log_prg(address, PRG_INDIRECT_CODE);
+                address
             }
             Addressing::IndirectIndexed => {
                 let pc = self.pc.wrapping_add(1);
@@ -435,6 +478,16 @@ impl CPU {
             }
         };
 
+        if logging {
+            let access = match addressing {
+                Addressing::IndexedIndirect | Addressing::IndirectIndexed => {
+                    PRG_DATA | PRG_INDIRECT_DATA
+                }
+                _ => PRG_DATA,
+            };
+            self.mem.set_cdl_access(access);
+        }
+
         self.pc += OP_SIZES[opcode as usize];
         cycles += OP_CYCLES[opcode as usize];
         if page_crossed && EXTRA_PAGECYCLE_OPS.contains(&opcode) {
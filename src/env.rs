@@ -0,0 +1,204 @@
+//! An environment for training agents, in the style of OpenAI Gym.
+//!
+//! The environment wraps a console, and exposes a discrete set of actions,
+//! observations of the frames the console produces, as well as rewards and
+//! episode ends computed from the state of the console.
+use crate::console::{Console, SaveState};
+use crate::controller::ButtonState;
+use crate::ports::{NullDevice, NES_HEIGHT, NES_WIDTH};
+
+/// Represents the different ways of turning a frame into an observation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObservationMode {
+    /// Every pixel is kept, with 3 bytes per pixel, in RGB order
+    Rgb,
+    /// Every pixel is kept, with a single byte for the brightness
+    Grayscale,
+    /// Pixels are converted to grayscale, and averaged in squares of this size
+    Downsampled(usize),
+}
+
+/// An observation of a frame of the game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub width: usize,
+    pub height: usize,
+    /// How many bytes each pixel uses
+    pub channels: usize,
+    /// The pixels, in row order
+    pub data: Vec<u8>,
+}
+
+/// Extra information returned after taking a step in the environment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepInfo {
+    /// The total reward accumulated over the frames of this step
+    pub reward: f32,
+    /// Whether or not the episode is over
+    pub done: bool,
+    /// How many frames were actually run, which may be less than the
+    /// frame skip if the episode ended early
+    pub frames: u32,
+}
+
+/// Returns the brightness of an ARGB pixel
+fn luminance(argb: u32) -> f32 {
+    let [_, r, g, b] = argb.to_be_bytes();
+    0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b)
+}
+
+/// An environment wrapping a console, to train agents on a game.
+///
+/// Each episode starts from a save state, which is the state of the console
+/// when the environment was created, unless changed with `set_start_state`.
+pub struct Env {
+    console: Console,
+    start: SaveState,
+    actions: Vec<ButtonState>,
+    frame_skip: u32,
+    mode: ObservationMode,
+    reward: Box<dyn FnMut(&Console) -> f32>,
+    done: Box<dyn FnMut(&Console) -> bool>,
+}
+
+impl Env {
+    /// Creates a new environment, with a set of possible actions.
+    ///
+    /// By default, each step lasts a single frame, observations are RGB,
+    /// there are no rewards, and episodes never end.
+    pub fn new(console: Console, actions: Vec<ButtonState>) -> Self {
+        let start = console.save_state();
+        Env {
+            console,
+            start,
+            actions,
+            frame_skip: 1,
+            mode: ObservationMode::Rgb,
+            reward: Box::new(|_| 0.0),
+            done: Box::new(|_| false),
+        }
+    }
+
+    /// Sets how many frames each action gets repeated for.
+    pub fn with_frame_skip(mut self, frame_skip: u32) -> Self {
+        self.frame_skip = frame_skip.max(1);
+        self
+    }
+
+    /// Sets how observations are produced from frames.
+    pub fn with_observation(mut self, mode: ObservationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the function used to calculate the reward after each frame.
+    ///
+    /// The reward for a step is the sum of the rewards of its frames.
+    pub fn with_reward<F>(mut self, reward: F) -> Self
+    where
+        F: FnMut(&Console) -> f32 + 'static,
+    {
+        self.reward = Box::new(reward);
+        self
+    }
+
+    /// Sets the function used to check if the episode is over after each frame.
+    pub fn with_done<F>(mut self, done: F) -> Self
+    where
+        F: FnMut(&Console) -> bool + 'static,
+    {
+        self.done = Box::new(done);
+        self
+    }
+
+    /// Changes the state new episodes start from.
+    pub fn set_start_state(&mut self, state: SaveState) {
+        self.start = state;
+    }
+
+    /// Returns how many different actions can be taken.
+    pub fn action_count(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Returns the console this environment uses.
+    pub fn console(&self) -> &Console {
+        &self.console
+    }
+
+    /// Starts a new episode, returning the first observation.
+    pub fn reset(&mut self) -> Observation {
+        self.console.load_state(&self.start);
+        self.observe()
+    }
+
+    /// Takes an action, by holding its buttons for a number of frames.
+    ///
+    /// This will panic if the action isn't less than `action_count`.
+    pub fn step(&mut self, action: usize) -> (Observation, StepInfo) {
+        let buttons = self.actions[action];
+        self.console.update_controller(buttons);
+        let mut info = StepInfo {
+            reward: 0.0,
+            done: false,
+            frames: 0,
+        };
+        while info.frames < self.frame_skip && !info.done {
+            self.console.step_frame(&mut NullDevice, &mut NullDevice);
+            info.reward += (self.reward)(&self.console);
+            info.done = (self.done)(&self.console);
+            info.frames += 1;
+        }
+        (self.observe(), info)
+    }
+
+    /// Returns an observation of the last frame.
+    pub fn observe(&self) -> Observation {
+        let pixels = self.console.pixels().as_ref();
+        match self.mode {
+            ObservationMode::Rgb => {
+                let mut data = Vec::with_capacity(pixels.len() * 3);
+                for &argb in pixels {
+                    let [_, r, g, b] = argb.to_be_bytes();
+                    data.extend_from_slice(&[r, g, b]);
+                }
+                Observation {
+                    width: NES_WIDTH,
+                    height: NES_HEIGHT,
+                    channels: 3,
+                    data,
+                }
+            }
+            ObservationMode::Grayscale => Observation {
+                width: NES_WIDTH,
+                height: NES_HEIGHT,
+                channels: 1,
+                data: pixels.iter().map(|&p| luminance(p) as u8).collect(),
+            },
+            ObservationMode::Downsampled(factor) => {
+                let factor = factor.max(1);
+                let width = NES_WIDTH / factor;
+                let height = NES_HEIGHT / factor;
+                let mut data = Vec::with_capacity(width * height);
+                for y in 0..height {
+                    for x in 0..width {
+                        let mut total = 0.0;
+                        for dy in 0..factor {
+                            let row = (y * factor + dy) * NES_WIDTH;
+                            for dx in 0..factor {
+                                total += luminance(pixels[row + x * factor + dx]);
+                            }
+                        }
+                        data.push((total / (factor * factor) as f32) as u8);
+                    }
+                }
+                Observation {
+                    width,
+                    height,
+                    channels: 1,
+                    data,
+                }
+            }
+        }
+    }
+}
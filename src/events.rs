@@ -0,0 +1,102 @@
+//! Recording when things happen during a frame, to debug raster effects.
+//!
+//! Each event is tagged with the scanline and dot the PPU was at, which
+//! makes it possible to see whether a write to the scroll registers lands
+//! in hblank, or which scanline an IRQ fires on. Since the CPU runs whole
+//! instructions before the PPU catches up, writes are tagged with the
+//! position the PPU was at when the instruction doing the write started.
+//!
+//! This is similar to Mesen's event viewer.
+use std::mem;
+
+/// The different kinds of events that get recorded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    /// A write to a PPU register, $2000 through $2007, or OAM DMA through $4014.
+    ///
+    /// Mirrors of the registers are reported as the register itself.
+    PpuWrite { register: u16, value: u8 },
+    /// A write to the registers of the mapper, at $8000 and above
+    MapperWrite { address: u16, value: u8 },
+    /// The sprite 0 hit flag got set
+    Sprite0Hit,
+    /// The PPU asserted an NMI
+    Nmi,
+    /// The mapper, or the APU frame counter, asserted an IRQ
+    Irq,
+}
+
+/// Something that happened at a given point in a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Event {
+    /// The scanline, from 0 to 261, with 261 being the pre-render line
+    pub scanline: u16,
+    /// The dot in the scanline, from 0 to 340
+    pub dot: u16,
+    pub kind: EventKind,
+}
+
+/// The events of the last complete frame, and of the frame being drawn.
+///
+/// Frames start on scanline 0, so the events of vblank are grouped
+/// with the frame drawn before them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventLog {
+    current: Vec<Event>,
+    last: Vec<Event>,
+    /// How many frames have been completed since logging started
+    frames: u64,
+    /// Where the PPU currently is, as (scanline, dot)
+    pub(crate) position: (u16, u16),
+    /// Whether the mapper was holding the IRQ line on the previous cycle
+    mapper_irq: bool,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        EventLog::default()
+    }
+
+    /// Returns the events of the last complete frame, in order.
+    pub fn events(&self) -> &[Event] {
+        &self.last
+    }
+
+    /// Returns the events of the frame being drawn so far, in order.
+    pub fn current_events(&self) -> &[Event] {
+        &self.current
+    }
+
+    /// Returns the events of the last complete frame on a single scanline.
+    pub fn scanline_events(&self, scanline: u16) -> impl Iterator<Item = &Event> {
+        self.last.iter().filter(move |e| e.scanline == scanline)
+    }
+
+    /// Returns how many frames have been completed since logging started.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub(crate) fn record(&mut self, kind: EventKind) {
+        let (scanline, dot) = self.position;
+        self.current.push(Event {
+            scanline,
+            dot,
+            kind,
+        });
+    }
+
+    /// Records an IRQ from the mapper, only when it starts holding the line.
+    pub(crate) fn mapper_irq(&mut self, irq: bool) {
+        if irq && !self.mapper_irq {
+            self.record(EventKind::Irq);
+        }
+        self.mapper_irq = irq;
+    }
+
+    /// Moves the events of the frame being drawn into the last frame.
+    pub(crate) fn start_frame(&mut self) {
+        self.last = mem::take(&mut self.current);
+        self.frames += 1;
+    }
+}
@@ -0,0 +1,515 @@
+//! A server for the GDB remote serial protocol, to debug games from editors.
+//!
+//! The server exposes the registers of the CPU, its address space,
+//! breakpoints, and single stepping. Since GDB doesn't know about the 6502,
+//! the registers are described to it with a target description.
+//!
+//! Memory writes at $6000 and above go through the mapper, just like writes
+//! from the game, so writing to ROM can switch banks or program flash.
+//! Writes to the IO registers, between $2000 and $5FFF, are refused.
+//!
+//! More info: https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::console::Console;
+use crate::hooks::Registers;
+use crate::ports::{AudioDevice, VideoDevice};
+
+/// The byte GDB sends to interrupt a running program
+const INTERRUPT: u8 = 0x03;
+/// How many instructions to run between checks for an interrupt
+const INTERRUPT_CHECK_INTERVAL: u32 = 1000;
+/// The signal reported when stopping, which is always SIGTRAP
+const STOP_REPLY: &str = "S05";
+/// The largest packet the server accepts, advertised in the reply to `qSupported`
+const PACKET_SIZE: usize = 0x1000;
+
+/// Describes the registers, in the order `g` packets send them
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.ludus.6502">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8"/>
+    <reg name="y" bitsize="8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// A connection to a debugger.
+pub trait GdbConnection: Read + Write {
+    /// Checks if the debugger has sent a byte, without waiting for one.
+    ///
+    /// Connections that can't do this never get interrupted while running.
+    fn try_read_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(None)
+    }
+}
+
+impl GdbConnection for TcpStream {
+    fn try_read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.set_nonblocking(true)?;
+        let mut byte = [0];
+        let res = match self.read(&mut byte) {
+            Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        };
+        self.set_nonblocking(false)?;
+        res
+    }
+}
+
+/// A connection through stdin and stdout, for debuggers that start
+/// the emulator themselves, with `target remote | command`.
+pub struct StdioConnection {
+    stdin: io::Stdin,
+    stdout: io::Stdout,
+}
+
+impl StdioConnection {
+    pub fn new() -> Self {
+        StdioConnection {
+            stdin: io::stdin(),
+            stdout: io::stdout(),
+        }
+    }
+}
+
+impl Default for StdioConnection {
+    fn default() -> Self {
+        StdioConnection::new()
+    }
+}
+
+impl Read for StdioConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdin.read(buf)
+    }
+}
+
+impl Write for StdioConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+impl GdbConnection for StdioConnection {}
+
+/// What the server should do after handling a packet
+enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    Detach,
+    /// The debugger killed the program, and doesn't expect a reply
+    Kill,
+}
+
+/// Serves a single debugger session, controlling a console.
+pub struct GdbServer<C: GdbConnection> {
+    conn: C,
+    breakpoints: BTreeSet<u16>,
+    /// Whether the debugger asked to stop acknowledging packets
+    no_ack: bool,
+    /// Bytes read while checking for an interrupt, which belong to the next packet
+    pending: VecDeque<u8>,
+}
+
+impl GdbServer<TcpStream> {
+    /// Waits for a debugger to connect to a local address, like `127.0.0.1:2345`.
+    pub fn accept<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(GdbServer::new(stream))
+    }
+}
+
+impl<C: GdbConnection> GdbServer<C> {
+    pub fn new(conn: C) -> Self {
+        GdbServer {
+            conn,
+            breakpoints: BTreeSet::new(),
+            no_ack: false,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns the addresses with breakpoints on them.
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    /// Handles packets from the debugger until it detaches.
+    ///
+    /// The console only runs while the debugger has it continue or step,
+    /// which is why this needs the audio and video devices.
+    pub fn serve<A, V>(
+        &mut self,
+        console: &mut Console,
+        audio: &mut A,
+        video: &mut V,
+    ) -> io::Result<()>
+    where
+        A: AudioDevice,
+        V: VideoDevice,
+    {
+        loop {
+            let packet = match self.read_packet() {
+                Ok(Some(packet)) => packet,
+                // Being interrupted while stopped doesn't change anything
+                Ok(None) => {
+                    self.write_packet(STOP_REPLY)?;
+                    continue;
+                }
+                // The debugger hanging up ends the session too
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            match self.handle(console, &packet) {
+                Action::Reply(reply) => self.write_packet(&reply)?,
+                Action::Step => {
+                    step_instruction(console, audio, video);
+                    self.write_packet(STOP_REPLY)?;
+                }
+                Action::Continue => {
+                    self.run(console, audio, video)?;
+                    self.write_packet(STOP_REPLY)?;
+                }
+                Action::Detach => {
+                    self.write_packet("OK")?;
+                    return Ok(());
+                }
+                Action::Kill => return Ok(()),
+            }
+        }
+    }
+
+    /// Runs the console until it hits a breakpoint, or gets interrupted.
+    fn run<A, V>(&mut self, console: &mut Console, audio: &mut A, video: &mut V) -> io::Result<()>
+    where
+        A: AudioDevice,
+        V: VideoDevice,
+    {
+        let mut until_check = INTERRUPT_CHECK_INTERVAL;
+        loop {
+            step_instruction(console, audio, video);
+            if self.breakpoints.contains(&console.registers().pc) {
+                return Ok(());
+            }
+            until_check -= 1;
+            if until_check == 0 {
+                until_check = INTERRUPT_CHECK_INTERVAL;
+                match self.conn.try_read_byte()? {
+                    Some(INTERRUPT) => return Ok(()),
+                    Some(byte) => self.pending.push_back(byte),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, console: &mut Console, packet: &str) -> Action {
+        let reply = |s: &str| Action::Reply(s.to_string());
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        match command {
+            "?" => reply(STOP_REPLY),
+            "g" => Action::Reply(encode_registers(console.registers())),
+            "G" => match decode_registers(args) {
+                Some(registers) => {
+                    console.set_registers(registers);
+                    reply("OK")
+                }
+                None => reply("E01"),
+            },
+            "p" => match read_register(console, args) {
+                Some(value) => Action::Reply(value),
+                None => reply("E01"),
+            },
+            "P" => match write_register(console, args) {
+                Some(()) => reply("OK"),
+                None => reply("E01"),
+            },
+            "m" => match parse_memory_range(args) {
+                Some((address, len)) => {
+                    let bytes = (0..len).map(|i| console.peek(address.wrapping_add(i as u16)));
+                    Action::Reply(bytes.map(|b| format!("{:02x}", b)).collect())
+                }
+                None => reply("E01"),
+            },
+            "M" => match write_memory(console, args) {
+                Some(()) => reply("OK"),
+                None => reply("E01"),
+            },
+            // Software and hardware breakpoints are treated the same way
+            "Z" | "z" => match parse_breakpoint(args) {
+                Some(address) => {
+                    if command == "Z" {
+                        self.breakpoints.insert(address);
+                    } else {
+                        self.breakpoints.remove(&address);
+                    }
+                    reply("OK")
+                }
+                None => reply(""),
+            },
+            "c" | "s" => {
+                if let Ok(address) = u16::from_str_radix(args, 16) {
+                    let mut registers = console.registers();
+                    registers.pc = address;
+                    console.set_registers(registers);
+                }
+                if command == "c" {
+                    Action::Continue
+                } else {
+                    Action::Step
+                }
+            }
+            "H" => reply("OK"),
+            "D" => Action::Detach,
+            "k" => Action::Kill,
+            "q" | "Q" => self.handle_query(packet),
+            _ => reply(""),
+        }
+    }
+
+    fn handle_query(&mut self, packet: &str) -> Action {
+        let reply = |s: &str| Action::Reply(s.to_string());
+        if packet.starts_with("qSupported") {
+            Action::Reply(format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+",
+                PACKET_SIZE
+            ))
+        } else if packet == "QStartNoAckMode" {
+            self.no_ack = true;
+            reply("OK")
+        } else if packet == "qAttached" {
+            reply("1")
+        } else if packet == "qC" {
+            reply("QC1")
+        } else if packet == "qfThreadInfo" {
+            reply("m1")
+        } else if packet == "qsThreadInfo" {
+            reply("l")
+        } else if let Some(rest) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(rest) {
+                Some((offset, len)) => {
+                    let start = (offset as usize).min(TARGET_XML.len());
+                    let end = start.saturating_add(len).min(TARGET_XML.len());
+                    let prefix = if end == TARGET_XML.len() { "l" } else { "m" };
+                    Action::Reply(format!("{}{}", prefix, &TARGET_XML[start..end]))
+                }
+                None => reply("E01"),
+            }
+        } else {
+            reply("")
+        }
+    }
+
+    /// Reads the next packet, acknowledging it.
+    ///
+    /// This returns None if the debugger sent an interrupt instead.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                b'$' => {}
+                INTERRUPT => return Ok(None),
+                // Acknowledgements, and anything else between packets
+                _ => continue,
+            }
+            let mut data = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => {
+                        sum = sum.wrapping_add(byte);
+                        data.push(byte);
+                    }
+                }
+            }
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok());
+            if self.no_ack {
+                return Ok(Some(unescape(&data)));
+            }
+            if expected == Some(sum) {
+                self.conn.write_all(b"+")?;
+                return Ok(Some(unescape(&data)));
+            }
+            self.conn.write_all(b"-")?;
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(byte);
+        }
+        let mut byte = [0];
+        self.conn.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+        write!(self.conn, "${}#{:02x}", data, sum)?;
+        self.conn.flush()
+    }
+}
+
+/// Runs the console until the CPU has executed an instruction.
+///
+/// Cycles where the CPU is stalled by DMA take a single cycle, while
+/// every instruction takes at least two.
+fn step_instruction<A, V>(console: &mut Console, audio: &mut A, video: &mut V)
+where
+    A: AudioDevice,
+    V: VideoDevice,
+{
+    while console.step(audio, video) == 1 {}
+}
+
+/// Undoes the escaping of `}`, `#`, `$`, and `*` in binary data
+fn unescape(data: &[u8]) -> String {
+    let mut res = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'}' {
+            if let Some(&next) = bytes.next() {
+                res.push(next ^ 0x20);
+            }
+        } else {
+            res.push(byte);
+        }
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+/// Returns each register as little endian bytes, in the order of the target description
+fn register_bytes(registers: Registers) -> [Vec<u8>; 6] {
+    [
+        vec![registers.a],
+        vec![registers.x],
+        vec![registers.y],
+        vec![registers.p],
+        vec![registers.sp],
+        registers.pc.to_le_bytes().to_vec(),
+    ]
+}
+
+fn encode_registers(registers: Registers) -> String {
+    let bytes = register_bytes(registers);
+    bytes
+        .iter()
+        .flatten()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode_registers(hex: &str) -> Option<Registers> {
+    let bytes = decode_hex(hex)?;
+    if bytes.len() != 7 {
+        return None;
+    }
+    Some(Registers {
+        a: bytes[0],
+        x: bytes[1],
+        y: bytes[2],
+        p: bytes[3],
+        sp: bytes[4],
+        pc: u16::from_le_bytes([bytes[5], bytes[6]]),
+    })
+}
+
+fn read_register(console: &Console, args: &str) -> Option<String> {
+    let n = usize::from_str_radix(args, 16).ok()?;
+    let bytes = register_bytes(console.registers());
+    let register = bytes.get(n)?;
+    Some(register.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn write_register(console: &mut Console, args: &str) -> Option<()> {
+    let equals = args.find('=')?;
+    let n = usize::from_str_radix(&args[..equals], 16).ok()?;
+    let value = decode_hex(&args[equals + 1..])?;
+    let mut registers = console.registers();
+    match (n, value.as_slice()) {
+        (0, &[v]) => registers.a = v,
+        (1, &[v]) => registers.x = v,
+        (2, &[v]) => registers.y = v,
+        (3, &[v]) => registers.p = v,
+        (4, &[v]) => registers.sp = v,
+        (5, &[lo, hi]) => registers.pc = u16::from_le_bytes([lo, hi]),
+        _ => return None,
+    }
+    console.set_registers(registers);
+    Some(())
+}
+
+fn write_memory(console: &mut Console, args: &str) -> Option<()> {
+    let colon = args.find(':')?;
+    let (address, len) = parse_memory_range(&args[..colon])?;
+    let bytes = decode_hex(&args[colon + 1..])?;
+    // Writing to IO registers would have side effects on the PPU, APU or inputs
+    let writable = |a: u16| !(0x2000..0x6000).contains(&a);
+    if bytes.len() != len || !(0..len).all(|i| writable(address.wrapping_add(i as u16))) {
+        return None;
+    }
+    for (i, byte) in bytes.into_iter().enumerate() {
+        let address = address.wrapping_add(i as u16);
+        if address < 0x2000 {
+            console.poke(address, byte);
+        } else {
+            console.write_cart(address, byte);
+        }
+    }
+    Some(())
+}
+
+/// Parses `address,length`, both in hexadecimal
+fn parse_range(args: &str) -> Option<(u16, usize)> {
+    let comma = args.find(',')?;
+    let address = u16::from_str_radix(&args[..comma], 16).ok()?;
+    let len = usize::from_str_radix(&args[comma + 1..], 16).ok()?;
+    Some((address, len))
+}
+
+/// Parses the range of an `m` or `M` packet.
+///
+/// Each byte takes two hex digits, so ranges that wouldn't fit in
+/// a packet are rejected, rather than reading the whole address space.
+fn parse_memory_range(args: &str) -> Option<(u16, usize)> {
+    parse_range(args).filter(|&(_, len)| len <= PACKET_SIZE / 2)
+}
+
+/// Parses the `type,address,kind` of a breakpoint packet, for types 0 and 1
+fn parse_breakpoint(args: &str) -> Option<u16> {
+    let mut parts = args.split(',');
+    match parts.next()? {
+        "0" | "1" => u16::from_str_radix(parts.next()?, 16).ok(),
+        _ => None,
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,259 @@
+//! Callbacks running when the CPU accesses memory, and at points in each frame.
+//!
+//! This is the layer scripting frontends can sit on top of. Hooks get a
+//! `HookContext`, letting them look at memory, and change it.
+//! Nothing is checked on the hot paths other than whether any hooks
+//! exist at all, so consoles without hooks run at full speed.
+use std::ops::RangeInclusive;
+
+use crate::memory::MemoryBus;
+use crate::symbols::Symbols;
+
+/// Identifies a hook, so that it can be removed later
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(usize);
+
+/// The registers of the CPU.
+///
+/// When execution hooks change these registers, the changes are written
+/// back to the CPU before the instruction runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Registers {
+    pub pc: u16,
+    pub sp: u8,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    /// The status flags, packed as they would be pushed to the stack
+    pub p: u8,
+}
+
+/// Gives hooks access to the memory of the console.
+pub struct HookContext<'a> {
+    bus: &'a mut MemoryBus,
+}
+
+impl<'a> HookContext<'a> {
+    pub(crate) fn new(bus: &'a mut MemoryBus) -> Self {
+        HookContext { bus }
+    }
+
+    /// Reads an address without any side effects, or triggering hooks.
+    ///
+    /// IO registers can't be read without side effects, so they read as 0.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+
+    /// Writes to work RAM or cartridge RAM, without triggering hooks.
+    ///
+    /// Writes to any other address are ignored, since they would talk
+    /// to the hardware instead of changing memory.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.bus.poke(address, value);
+    }
+
+    /// Returns the offset into PRG ROM that an address is currently mapped to.
+    ///
+    /// This is None for addresses outside of ROM, or if the mapper doesn't know.
+    pub fn prg_offset(&self, address: u16) -> Option<usize> {
+        if address >= 0x8000 {
+            self.bus.mapper.prg_offset(address)
+        } else {
+            None
+        }
+    }
+}
+
+type AccessHook = Box<dyn FnMut(&mut HookContext, u16, u8) -> u8 + Send>;
+type ExecHook = Box<dyn FnMut(&mut HookContext, &mut Registers) + Send>;
+type FrameHook = Box<dyn FnMut(&mut HookContext) + Send>;
+type ScanlineHook = Box<dyn FnMut(&mut HookContext, u16) + Send>;
+
+/// The hooks registered on a console.
+#[derive(Default)]
+pub struct Hooks {
+    next_id: usize,
+    reads: Vec<(HookId, RangeInclusive<u16>, AccessHook)>,
+    writes: Vec<(HookId, RangeInclusive<u16>, AccessHook)>,
+    execs: Vec<(HookId, RangeInclusive<u16>, ExecHook)>,
+    frame_starts: Vec<(HookId, FrameHook)>,
+    frame_ends: Vec<(HookId, FrameHook)>,
+    scanlines: Vec<(HookId, ScanlineHook)>,
+}
+
+impl Hooks {
+    fn next_id(&mut self) -> HookId {
+        self.next_id += 1;
+        HookId(self.next_id)
+    }
+
+    /// Runs a function whenever the CPU reads an address in a range.
+    ///
+    /// The function receives the address and the value read, and returns
+    /// the value the CPU should see instead.
+    pub fn on_read<F>(&mut self, range: RangeInclusive<u16>, f: F) -> HookId
+    where
+        F: FnMut(&mut HookContext, u16, u8) -> u8 + Send + 'static,
+    {
+        let id = self.next_id();
+        self.reads.push((id, range, Box::new(f)));
+        id
+    }
+
+    /// Runs a function whenever the CPU writes to an address in a range.
+    ///
+    /// The function receives the address and the value written, and returns
+    /// the value that should actually be written.
+    pub fn on_write<F>(&mut self, range: RangeInclusive<u16>, f: F) -> HookId
+    where
+        F: FnMut(&mut HookContext, u16, u8) -> u8 + Send + 'static,
+    {
+        let id = self.next_id();
+        self.writes.push((id, range, Box::new(f)));
+        id
+    }
+
+    /// Runs a function before the CPU executes an instruction in a range.
+    pub fn on_exec<F>(&mut self, range: RangeInclusive<u16>, f: F) -> HookId
+    where
+        F: FnMut(&mut HookContext, &mut Registers) + Send + 'static,
+    {
+        let id = self.next_id();
+        self.execs.push((id, range, Box::new(f)));
+        id
+    }
+
+    /// Runs a function before the CPU executes the instruction at a label.
+    ///
+    /// The label is written like in `Symbols::resolve_address`, such as
+    /// `main_loop+2` or `$C000`. Labels in ROM only trigger the hook when
+    /// their bank is mapped in. This returns None if the label doesn't exist.
+    pub fn on_exec_label<F>(&mut self, symbols: &Symbols, spec: &str, mut f: F) -> Option<HookId>
+    where
+        F: FnMut(&mut HookContext, &mut Registers) + Send + 'static,
+    {
+        let (address, prg_offset) = symbols.resolve_location(spec)?;
+        let hook = move |ctx: &mut HookContext, registers: &mut Registers| {
+            let in_bank = match (prg_offset, ctx.prg_offset(registers.pc)) {
+                (Some(expected), Some(offset)) => expected == offset,
+                _ => true,
+            };
+            if in_bank {
+                f(ctx, registers);
+            }
+        };
+        Some(self.on_exec(address..=address, hook))
+    }
+
+    /// Runs a function when the PPU starts drawing a frame, on scanline 0.
+    pub fn on_frame_start<F>(&mut self, f: F) -> HookId
+    where
+        F: FnMut(&mut HookContext) + Send + 'static,
+    {
+        let id = self.next_id();
+        self.frame_starts.push((id, Box::new(f)));
+        id
+    }
+
+    /// Runs a function when the PPU finishes a frame, at the start of vblank.
+    pub fn on_frame_end<F>(&mut self, f: F) -> HookId
+    where
+        F: FnMut(&mut HookContext) + Send + 'static,
+    {
+        let id = self.next_id();
+        self.frame_ends.push((id, Box::new(f)));
+        id
+    }
+
+    /// Runs a function at the start of every scanline, from 0 to 261.
+    pub fn on_scanline<F>(&mut self, f: F) -> HookId
+    where
+        F: FnMut(&mut HookContext, u16) + Send + 'static,
+    {
+        let id = self.next_id();
+        self.scanlines.push((id, Box::new(f)));
+        id
+    }
+
+    /// Removes a hook, returning false if it didn't exist.
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let before = self.len();
+        self.reads.retain(|h| h.0 != id);
+        self.writes.retain(|h| h.0 != id);
+        self.execs.retain(|h| h.0 != id);
+        self.frame_starts.retain(|h| h.0 != id);
+        self.frame_ends.retain(|h| h.0 != id);
+        self.scanlines.retain(|h| h.0 != id);
+        self.len() != before
+    }
+
+    /// Returns how many hooks are registered.
+    pub fn len(&self) -> usize {
+        self.reads.len()
+            + self.writes.len()
+            + self.execs.len()
+            + self.frame_starts.len()
+            + self.frame_ends.len()
+            + self.scanlines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn has_reads(&self) -> bool {
+        !self.reads.is_empty()
+    }
+
+    pub(crate) fn read(&mut self, ctx: &mut HookContext, address: u16, mut value: u8) -> u8 {
+        for (_, range, hook) in &mut self.reads {
+            if range.contains(&address) {
+                value = hook(ctx, address, value);
+            }
+        }
+        value
+    }
+
+    pub(crate) fn has_writes(&self) -> bool {
+        !self.writes.is_empty()
+    }
+
+    pub(crate) fn write(&mut self, ctx: &mut HookContext, address: u16, mut value: u8) -> u8 {
+        for (_, range, hook) in &mut self.writes {
+            if range.contains(&address) {
+                value = hook(ctx, address, value);
+            }
+        }
+        value
+    }
+
+    pub(crate) fn has_execs(&self) -> bool {
+        !self.execs.is_empty()
+    }
+
+    pub(crate) fn exec(&mut self, ctx: &mut HookContext, registers: &mut Registers) {
+        for (_, range, hook) in &mut self.execs {
+            if range.contains(&registers.pc) {
+                hook(ctx, registers);
+            }
+        }
+    }
+
+    pub(crate) fn frame_end(&mut self, ctx: &mut HookContext) {
+        for (_, hook) in &mut self.frame_ends {
+            hook(ctx);
+        }
+    }
+
+    pub(crate) fn scanline(&mut self, ctx: &mut HookContext, scanline: u16) {
+        if scanline == 0 {
+            for (_, hook) in &mut self.frame_starts {
+                hook(ctx);
+            }
+        }
+        for (_, hook) in &mut self.scanlines {
+            hook(ctx, scanline);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Devices that can be plugged into the controller ports.
+//!
+//! Both ports share the same strobe, written through $4016, and each port
+//! is read serially, through $4016 for the first port and $4017 for the second.
+//!
+//! More info: https://wiki.nesdev.com/w/index.php/Input_devices
+use std::any::Any;
+
+/// Represents a device plugged into one of the controller ports.
+///
+/// Only `write` and `read` are needed to implement a device, the other
+/// methods are for devices that need to know about timing, or about
+/// the picture on screen, like light guns.
+pub trait InputDevice: BoxedDevice + Send {
+    /// Receives a write to $4016.
+    ///
+    /// Bit 0 is the strobe, and bits 1 and 2 are the extra output lines
+    /// used by some Famicom expansion devices.
+    fn write(&mut self, value: u8);
+    /// Returns the 5 bits of the port, D0 through D4, when the CPU reads it.
+    ///
+    /// Standard controllers only use D0, but other devices use the rest.
+    fn read(&mut self) -> u8;
+    /// Called at the end of each frame, when vblank starts.
+    fn end_frame(&mut self) {}
+    /// Called at the end of each scanline.
+    fn end_scanline(&mut self) {}
+    /// Called for each pixel the PPU draws, with its color in ARGB format.
+    ///
+    /// This is only called if `senses_light` returns true.
+    fn sense_pixel(&mut self, _x: i32, _y: i32, _argb: u32) {}
+    /// Whether this device looks at the picture, through `sense_pixel`.
+    fn senses_light(&self) -> bool {
+        false
+    }
+}
+
+/// Allows cloning and downcasting boxed devices.
+///
+/// This is implemented automatically for every device implementing Clone.
+pub trait BoxedDevice {
+    fn clone_box(&self) -> Box<dyn InputDevice>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static + InputDevice + Clone> BoxedDevice for T {
+    fn clone_box(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn InputDevice> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// A port with nothing plugged into it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Unplugged;
+
+impl InputDevice for Unplugged {
+    fn write(&mut self, _value: u8) {}
+
+    fn read(&mut self) -> u8 {
+        0
+    }
+}
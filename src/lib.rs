@@ -1,13 +1,58 @@
+#[cfg(feature = "bk2")]
+extern crate miniz_oxide;
+#[cfg(feature = "bk2")]
+extern crate sha1_smol;
+
 pub(crate) mod apu;
+#[cfg(feature = "bk2")]
+pub mod bk2;
 pub mod cart;
+pub mod cdl;
+pub mod cheats;
 pub mod console;
 pub mod controller;
 pub(crate) mod cpu;
+pub mod env;
+pub mod events;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+pub mod hooks;
+pub mod input;
 pub(crate) mod memory;
+pub mod movie;
+pub mod multitap;
+pub mod palette;
+pub mod pool;
 pub mod ports;
 pub(crate) mod ppu;
+pub mod ram_search;
+pub mod symbols;
+pub mod test_rom;
+#[cfg(test)]
+mod tests;
+pub mod vaus;
+pub mod zapper;
 
+#[cfg(feature = "bk2")]
+pub use bk2::{Bk2, Bk2Error};
 pub use cart::{Cart, CartReadingError};
-pub use console::Console;
-pub use controller::ButtonState;
-pub use ports::{AudioDevice, PixelBuffer, VideoDevice, NES_HEIGHT, NES_WIDTH};
+pub use cdl::CodeDataLog;
+pub use cheats::{Cheat, CheatError, Cheats};
+pub use console::{Console, SaveState};
+pub use controller::{ButtonState, Controller, InputMacro, Turbo, TurboClock};
+pub use env::{Env, Observation, ObservationMode, StepInfo};
+pub use events::{Event, EventKind, EventLog};
+#[cfg(feature = "gdb")]
+pub use gdb::{GdbConnection, GdbServer, StdioConnection};
+pub use hooks::{HookContext, HookId, Hooks, Registers};
+pub use input::{InputDevice, Unplugged};
+pub use movie::{Movie, MovieError, MovieFrame};
+pub use multitap::{Multitap, MultitapMode};
+pub use palette::{BuiltinPalette, Palette, PaletteError};
+pub use pool::{ConsolePool, FrameBatch};
+pub use ports::{AudioDevice, Image, Layers, PixelBuffer, VideoDevice, NES_HEIGHT, NES_WIDTH};
+pub use ram_search::{Encoding, Filter, RamSearch, ValueSize};
+pub use symbols::{Label, SymbolError, Symbols};
+pub use test_rom::{run_test_rom, TestReport, TestStatus};
+pub use vaus::{Vaus, VausWiring};
+pub use zapper::Zapper;
@@ -1,9 +1,11 @@
 use crate::cart::{Cart, Mirroring};
 use crate::memory::Mapper;
+use std::sync::Arc;
 
 const PRG_BANK_SIZE: usize = 0x4000;
 const CHR_BANK_SIZE: usize = 0x1000;
 
+#[derive(Clone)]
 struct ShiftRegister {
     register: u8,
     count: u8,
@@ -55,6 +57,7 @@ impl From<u8> for PRGSwitching {
 }
 
 /// Represents the 32KB bank of PRG data
+#[derive(Clone)]
 struct PRGBanks {
     /// How many 16KB banks exist
     count: u8,
@@ -138,6 +141,7 @@ impl From<u8> for CHRSwitching {
     }
 }
 
+#[derive(Clone)]
 struct CHRBanks {
     count: u8,
     bank_0: usize,
@@ -209,6 +213,7 @@ impl CHRBanks {
 /// The mapper for iNES 1.
 ///
 /// More info: https://wiki.nesdev.com/w/index.php/MMC1
+#[derive(Clone)]
 pub struct Mapper1 {
     /// The cartridge data
     cart: Cart,
@@ -275,9 +280,18 @@ impl Mapper for Mapper1 {
         self.cart.mirroring
     }
 
+    fn prg_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg.index(address))
+    }
+
+    fn chr_offset(&self, address: u16) -> Option<usize> {
+        Some(self.chr.index(address))
+    }
+
     fn write(&mut self, address: u16, value: u8) {
         if address < 0x2000 {
-            self.cart.chr[self.chr.index(address)] = value;
+            let index = self.chr.index(address);
+            Arc::make_mut(&mut self.cart.chr)[index] = value;
         } else if address >= 0x8000 {
             if value & 0x80 != 0 {
                 self.shift_register = ShiftRegister::default();
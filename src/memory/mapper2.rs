@@ -1,8 +1,10 @@
 use crate::cart::{Cart, Mirroring};
 use crate::memory::Mapper;
+use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Mapper2 {
-    cart: Cart,
+    pub(super) cart: Cart,
     prg_banks: u8,
     prgbank1: usize,
     prgbank2: usize,
@@ -20,22 +22,28 @@ impl Mapper2 {
             prgbank2,
         }
     }
+
+    /// Switches the bank at $8000, wrapping around the number of banks
+    pub(super) fn switch_bank(&mut self, bank: u8) {
+        self.prgbank1 = (bank % self.prg_banks) as usize;
+    }
+
+    /// Returns the index into PRG of an address at $8000 or above
+    pub(super) fn prg_index(&self, address: u16) -> usize {
+        let bank = if address >= 0xC000 {
+            self.prgbank2
+        } else {
+            self.prgbank1
+        };
+        bank * 0x4000 + (address as usize) % 0x4000
+    }
 }
 
 impl Mapper for Mapper2 {
     fn read(&self, address: u16) -> u8 {
         match address {
             a if a < 0x2000 => self.cart.chr[a as usize],
-            a if a >= 0xC000 => {
-                let shifted = (address - 0xC000) as usize;
-                let index = self.prgbank2 * 0x4000 + shifted;
-                self.cart.prg[index]
-            }
-            a if a >= 0x8000 => {
-                let shifted = (address - 0x8000) as usize;
-                let index = self.prgbank1 * 0x4000 + shifted;
-                self.cart.prg[index]
-            }
+            a if a >= 0x8000 => self.cart.prg[self.prg_index(a)],
             a if a >= 0x6000 => {
                 let shifted = (address - 0x6000) as usize;
                 self.cart.sram[shifted]
@@ -50,13 +58,18 @@ impl Mapper for Mapper2 {
         self.cart.mirroring
     }
 
+    fn prg_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_index(address))
+    }
+
+    fn chr_offset(&self, address: u16) -> Option<usize> {
+        Some(address as usize)
+    }
+
     fn write(&mut self, address: u16, value: u8) {
         match address {
-            a if a < 0x2000 => self.cart.chr[a as usize] = value,
-            a if a >= 0x8000 => {
-                let bank = value % self.prg_banks;
-                self.prgbank1 = bank as usize;
-            }
+            a if a < 0x2000 => Arc::make_mut(&mut self.cart.chr)[a as usize] = value,
+            a if a >= 0x8000 => self.switch_bank(value),
             a if a >= 0x6000 => {
                 let shifted = (address - 0x6000) as usize;
                 self.cart.sram[shifted] = value;
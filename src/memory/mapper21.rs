@@ -0,0 +1,302 @@
+use crate::cart::{Cart, MapperID, Mirroring};
+use crate::memory::Mapper;
+use std::sync::Arc;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// Describes how a board connects the CPU address lines to the chip.
+///
+/// The chip has 2 register select pins, and each board variant wires
+/// different CPU address lines to them. A mask may contain multiple lines,
+/// which is how we handle carts where the submapper wasn't specified.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Wiring {
+    /// The CPU address lines connected to the first select pin
+    a0: u16,
+    /// The CPU address lines connected to the second select pin
+    a1: u16,
+    /// Whether or not this board uses a VRC2 instead of a VRC4
+    vrc2: bool,
+    /// How much to shift CHR bank numbers to the right.
+    ///
+    /// VRC2a ignores the lowest bit of each CHR bank register.
+    chr_shift: u8,
+}
+
+impl Wiring {
+    fn new(a0: u16, a1: u16, vrc2: bool) -> Self {
+        Wiring {
+            a0,
+            a1,
+            vrc2,
+            chr_shift: 0,
+        }
+    }
+
+    /// Figure out the wiring based on the mapper and submapper numbers.
+    ///
+    /// See: https://wiki.nesdev.com/w/index.php/VRC2_and_VRC4
+    fn from_cart(cart: &Cart) -> Self {
+        match (cart.mapper, cart.submapper) {
+            // VRC4a
+            (MapperID::M21, 1) => Wiring::new(0x02, 0x04, false),
+            // VRC4c
+            (MapperID::M21, 2) => Wiring::new(0x40, 0x80, false),
+            (MapperID::M21, _) => Wiring::new(0x42, 0x84, false),
+            // VRC2a
+            (MapperID::M22, _) => Wiring {
+                chr_shift: 1,
+                ..Wiring::new(0x02, 0x01, true)
+            },
+            // VRC4f
+            (MapperID::M23, 1) => Wiring::new(0x01, 0x02, false),
+            // VRC4e
+            (MapperID::M23, 2) => Wiring::new(0x04, 0x08, false),
+            // VRC2b
+            (MapperID::M23, 3) => Wiring::new(0x01, 0x02, true),
+            (MapperID::M23, _) => Wiring::new(0x05, 0x0A, false),
+            // VRC4b
+            (MapperID::M25, 1) => Wiring::new(0x02, 0x01, false),
+            // VRC4d
+            (MapperID::M25, 2) => Wiring::new(0x08, 0x04, false),
+            // VRC2c
+            (MapperID::M25, 3) => Wiring::new(0x02, 0x01, true),
+            (MapperID::M25, _) => Wiring::new(0x0A, 0x05, false),
+            (mapper, _) => unreachable!("Mapper21 used for {:?}", mapper),
+        }
+    }
+
+    /// Translates a CPU address into the register it selects.
+    ///
+    /// Registers are returned as $X000 through $X003, the way they're
+    /// numbered for the VRC4f, which has the simplest wiring.
+    fn decode(self, address: u16) -> u16 {
+        let mut register = address & 0xF000;
+        if address & self.a0 != 0 {
+            register |= 1;
+        }
+        if address & self.a1 != 0 {
+            register |= 2;
+        }
+        register
+    }
+}
+
+/// The IRQ counter shared between VRC4 and some other Konami chips.
+///
+/// More info: https://wiki.nesdev.com/w/index.php/VRC_IRQ
+#[derive(Clone, Default)]
+struct IRQCounter {
+    /// The value the counter gets reloaded with
+    latch: u8,
+    /// The current value of the counter, firing when it wraps around
+    counter: u8,
+    /// Counts CPU cycles in scanline mode, approximating 341 PPU cycles
+    prescaler: i16,
+    /// Whether or not the counter is running
+    enabled: bool,
+    /// The value `enabled` gets set to when acknowledging
+    enable_after_ack: bool,
+    /// Whether we count CPU cycles directly, instead of scanlines
+    cycle_mode: bool,
+    /// Whether or not the IRQ line is being asserted
+    pending: bool,
+}
+
+impl IRQCounter {
+    fn write_latch_low(&mut self, value: u8) {
+        self.latch = (self.latch & 0xF0) | (value & 0xF);
+    }
+
+    fn write_latch_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x0F) | ((value & 0xF) << 4);
+    }
+
+    fn write_control(&mut self, value: u8) {
+        self.enable_after_ack = value & 1 != 0;
+        self.enabled = value & 2 != 0;
+        self.cycle_mode = value & 4 != 0;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+        self.pending = false;
+    }
+
+    fn acknowledge(&mut self) {
+        self.enabled = self.enable_after_ack;
+        self.pending = false;
+    }
+
+    fn clock(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    fn step(&mut self) -> bool {
+        if self.enabled {
+            if self.cycle_mode {
+                self.clock();
+            } else {
+                self.prescaler -= 3;
+                if self.prescaler <= 0 {
+                    self.prescaler += 341;
+                    self.clock();
+                }
+            }
+        }
+        self.pending
+    }
+}
+
+/// The mapper for iNES 21, 22, 23 and 25.
+///
+/// These all use some variant of the Konami VRC2 or VRC4 chips, and only
+/// differ by the way they're wired to the cartridge.
+///
+/// More info: https://wiki.nesdev.com/w/index.php/VRC2_and_VRC4
+#[derive(Clone)]
+pub struct Mapper21 {
+    /// The cartridge data
+    cart: Cart,
+    /// How this specific board is wired
+    wiring: Wiring,
+    /// How many 8KB PRG banks exist
+    prg_banks: usize,
+    /// How many 1KB CHR banks exist
+    chr_banks: usize,
+    /// The 2 switchable 8KB PRG banks
+    prg_select: [usize; 2],
+    /// If set, the first switchable bank is at $C000 instead of $8000
+    prg_swapped: bool,
+    /// The 8 switchable 1KB CHR banks, stored as full 9 bit numbers
+    chr_select: [u16; 8],
+    irq: IRQCounter,
+}
+
+impl Mapper21 {
+    pub fn new(cart: Cart) -> Self {
+        let wiring = Wiring::from_cart(&cart);
+        let prg_banks = cart.prg.len() / PRG_BANK_SIZE;
+        let chr_banks = cart.chr.len() / CHR_BANK_SIZE;
+        Mapper21 {
+            cart,
+            wiring,
+            prg_banks,
+            chr_banks,
+            prg_select: [0, 1],
+            prg_swapped: false,
+            chr_select: [0; 8],
+            irq: IRQCounter::default(),
+        }
+    }
+
+    fn prg_index(&self, address: u16) -> usize {
+        let last = self.prg_banks - 1;
+        let bank = match (address, self.prg_swapped) {
+            (0x8000..=0x9FFF, false) => self.prg_select[0],
+            (0x8000..=0x9FFF, true) => last - 1,
+            (0xA000..=0xBFFF, _) => self.prg_select[1],
+            (0xC000..=0xDFFF, false) => last - 1,
+            (0xC000..=0xDFFF, true) => self.prg_select[0],
+            _ => last,
+        };
+        let shift = (address as usize) % PRG_BANK_SIZE;
+        (bank % self.prg_banks) * PRG_BANK_SIZE + shift
+    }
+
+    fn chr_index(&self, address: u16) -> usize {
+        let slot = (address as usize) / CHR_BANK_SIZE;
+        let bank = (self.chr_select[slot] >> self.wiring.chr_shift) as usize;
+        let shift = (address as usize) % CHR_BANK_SIZE;
+        (bank % self.chr_banks) * CHR_BANK_SIZE + shift
+    }
+
+    fn write_mirroring(&mut self, value: u8) {
+        let mode = if self.wiring.vrc2 { value & 1 } else { value & 3 };
+        self.cart.mirroring = match mode {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleLower,
+            _ => Mirroring::SingleUpper,
+        };
+    }
+
+    fn write_chr(&mut self, register: u16, value: u8) {
+        // Each pair of registers controls the low and high half of a bank
+        let slot = (((register - 0xB000) >> 11) | ((register >> 1) & 1)) as usize;
+        let bank = &mut self.chr_select[slot];
+        if register & 1 == 0 {
+            *bank = (*bank & 0x1F0) | u16::from(value & 0xF);
+        } else {
+            *bank = (*bank & 0xF) | (u16::from(value & 0x1F) << 4);
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        let register = self.wiring.decode(address);
+        match register {
+            0x8000..=0x8003 => self.prg_select[0] = (value & 0x1F) as usize,
+            0x9000..=0x9003 if self.wiring.vrc2 => self.write_mirroring(value),
+            0x9000 | 0x9001 => self.write_mirroring(value),
+            0x9002 | 0x9003 => self.prg_swapped = value & 2 != 0,
+            0xA000..=0xA003 => self.prg_select[1] = (value & 0x1F) as usize,
+            0xB000..=0xEFFF => self.write_chr(register, value),
+            _ if self.wiring.vrc2 => {}
+            0xF000 => self.irq.write_latch_low(value),
+            0xF001 => self.irq.write_latch_high(value),
+            0xF002 => self.irq.write_control(value),
+            _ => self.irq.acknowledge(),
+        }
+    }
+}
+
+impl Mapper for Mapper21 {
+    fn read(&self, address: u16) -> u8 {
+        if address < 0x2000 {
+            self.cart.chr[self.chr_index(address)]
+        } else if address >= 0x8000 {
+            self.cart.prg[self.prg_index(address)]
+        } else if address >= 0x6000 {
+            let shift = address - 0x6000;
+            self.cart.sram[shift as usize]
+        } else {
+            panic!("Mapper21 unhandled read at {:X}", address);
+        }
+    }
+
+    fn mirroring_mode(&self) -> Mirroring {
+        self.cart.mirroring
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address < 0x2000 {
+            let index = self.chr_index(address);
+            Arc::make_mut(&mut self.cart.chr)[index] = value;
+        } else if address >= 0x8000 {
+            self.write_register(address, value);
+        } else if address >= 0x6000 {
+            let shift = address - 0x6000;
+            self.cart.sram[shift as usize] = value;
+        } else {
+            panic!("Mapper21 unhandled write at {:X}", address);
+        }
+    }
+
+    fn step(&mut self) -> bool {
+        self.irq.step()
+    }
+
+    fn prg_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_index(address))
+    }
+
+    fn chr_offset(&self, address: u16) -> Option<usize> {
+        Some(self.chr_index(address))
+    }
+}
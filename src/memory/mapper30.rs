@@ -0,0 +1,198 @@
+use crate::cart::{Cart, Mirroring};
+use crate::memory::mapper2::Mapper2;
+use crate::memory::Mapper;
+use std::sync::Arc;
+
+const CHR_BANK_SIZE: usize = 0x2000;
+/// How much CHR RAM these boards have
+const CHR_RAM_SIZE: usize = 0x8000;
+/// The size of the sectors erased by the flash chip
+const SECTOR_SIZE: usize = 0x1000;
+/// The manufacturer and device ID for the SST39SF040
+const FLASH_ID: [u8; 2] = [0xBF, 0xB7];
+
+/// Represents how far along a command sequence the flash chip is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FlashState {
+    /// Waiting for the start of a command
+    Idle,
+    /// Received the first unlock byte
+    Unlock1,
+    /// Received both unlock bytes, and are waiting for a command
+    Unlock2,
+    /// The next write will program a byte
+    Program,
+    /// Received the erase command, and waiting for the second unlock
+    Erase,
+    /// Received the first unlock byte after an erase command
+    EraseUnlock1,
+    /// Received both unlock bytes, and are waiting for what to erase
+    EraseUnlock2,
+    /// Reads return the chip identifier, until we're told to exit
+    SoftwareID,
+}
+
+/// Emulates the command interface of the flash chip holding PRG.
+///
+/// More info: https://wiki.nesdev.com/w/index.php/UNROM_512#Flash_Writes
+#[derive(Clone)]
+struct Flash {
+    state: FlashState,
+}
+
+impl Flash {
+    fn new() -> Self {
+        Flash {
+            state: FlashState::Idle,
+        }
+    }
+
+    /// Feeds a write into the chip, modifying PRG if a command completes.
+    ///
+    /// The address here is relative to the start of the chip.
+    fn write(&mut self, prg: &mut [u8], address: usize, value: u8) {
+        let command = address & 0x7FFF;
+        self.state = match (self.state, command, value) {
+            (FlashState::Program, _, _) => {
+                // Programming can only clear bits, erasing is needed to set them
+                prg[address] &= value;
+                FlashState::Idle
+            }
+            (FlashState::Idle, 0x5555, 0xAA) => FlashState::Unlock1,
+            (FlashState::Unlock1, 0x2AAA, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, 0x5555, 0xA0) => FlashState::Program,
+            (FlashState::Unlock2, 0x5555, 0x80) => FlashState::Erase,
+            (FlashState::Unlock2, 0x5555, 0x90) => FlashState::SoftwareID,
+            (FlashState::Erase, 0x5555, 0xAA) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, 0x2AAA, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                let start = address - address % SECTOR_SIZE;
+                for byte in prg[start..start + SECTOR_SIZE].iter_mut() {
+                    *byte = 0xFF;
+                }
+                FlashState::Idle
+            }
+            (FlashState::EraseUnlock2, 0x5555, 0x10) => {
+                for byte in prg.iter_mut() {
+                    *byte = 0xFF;
+                }
+                FlashState::Idle
+            }
+            // Staying in ID mode unless we get the exit command
+            (FlashState::SoftwareID, _, v) if v != 0xF0 => FlashState::SoftwareID,
+            _ => FlashState::Idle,
+        }
+    }
+
+    /// Returns the chip identifier if we're in software ID mode
+    fn read_id(&self, address: u16) -> Option<u8> {
+        if self.state == FlashState::SoftwareID {
+            Some(FLASH_ID[(address & 1) as usize])
+        } else {
+            None
+        }
+    }
+}
+
+/// The mapper for iNES 30, also known as UNROM 512.
+///
+/// This works like UxROM, but adds CHR RAM banking, a switchable single
+/// screen mirroring, and lets games rewrite their PRG to store saves.
+/// The PRG banking is left to the UxROM mapper this wraps.
+///
+/// More info: https://wiki.nesdev.com/w/index.php/UNROM_512
+#[derive(Clone)]
+pub struct Mapper30 {
+    uxrom: Mapper2,
+    chr_banks: u8,
+    chrbank: usize,
+    /// Whether or not the game controls single screen mirroring
+    one_screen: bool,
+    /// When set, writes to $8000-$BFFF go to the flash chip
+    flashable: bool,
+    flash: Flash,
+}
+
+impl Mapper30 {
+    pub fn new(mut cart: Cart) -> Self {
+        if cart.chr.len() < CHR_RAM_SIZE {
+            Arc::make_mut(&mut cart.chr).resize(CHR_RAM_SIZE, 0);
+        }
+        let chr_banks = cart.chr.len() / CHR_BANK_SIZE;
+        let one_screen = matches!(
+            cart.mirroring,
+            Mirroring::SingleLower | Mirroring::SingleUpper
+        );
+        // The battery bit is used to indicate a self flashable board
+        let flashable = cart.has_battery;
+        Mapper30 {
+            uxrom: Mapper2::new(cart),
+            chr_banks: chr_banks as u8,
+            chrbank: 0,
+            one_screen,
+            flashable,
+            flash: Flash::new(),
+        }
+    }
+
+    fn write_bank(&mut self, value: u8) {
+        self.uxrom.switch_bank(value & 0x1F);
+        self.chrbank = (((value >> 5) & 3) % self.chr_banks) as usize;
+        if self.one_screen {
+            self.uxrom.cart.mirroring = if value & 0x80 == 0 {
+                Mirroring::SingleLower
+            } else {
+                Mirroring::SingleUpper
+            };
+        }
+    }
+}
+
+impl Mapper for Mapper30 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            a if a < 0x2000 => {
+                let index = self.chrbank * CHR_BANK_SIZE + a as usize;
+                self.uxrom.cart.chr[index]
+            }
+            a if a >= 0x8000 => self.flash.read_id(a).unwrap_or_else(|| self.uxrom.read(a)),
+            a => self.uxrom.read(a),
+        }
+    }
+
+    fn mirroring_mode(&self) -> Mirroring {
+        self.uxrom.mirroring_mode()
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            a if a < 0x2000 => {
+                let index = self.chrbank * CHR_BANK_SIZE + a as usize;
+                Arc::make_mut(&mut self.uxrom.cart.chr)[index] = value;
+            }
+            0x8000..=0xBFFF if self.flashable => {
+                let index = self.uxrom.prg_index(address);
+                let prg = Arc::make_mut(&mut self.uxrom.cart.prg);
+                self.flash.write(prg, index, value);
+            }
+            a if a >= 0x8000 => self.write_bank(value),
+            a => self.uxrom.write(a, value),
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> Option<usize> {
+        self.uxrom.prg_offset(address)
+    }
+
+    fn chr_offset(&self, address: u16) -> Option<usize> {
+        Some(self.chrbank * CHR_BANK_SIZE + address as usize)
+    }
+
+    fn flash(&self) -> Option<&[u8]> {
+        if self.flashable {
+            Some(&self.uxrom.cart.prg)
+        } else {
+            None
+        }
+    }
+}
@@ -1,17 +1,67 @@
 mod mapper1;
 mod mapper2;
+mod mapper21;
+mod mapper30;
 
 use super::apu::APUState;
 use super::cart::{Cart, MapperID, Mirroring};
+use super::cdl::{CodeDataLog, CHR_READ, CHR_RENDERED, PRG_PCM};
+use super::cheats::Cheats;
 use super::controller::Controller;
 use super::cpu::CPUState;
+use super::events::{EventKind, EventLog};
+use super::hooks::{HookContext, Hooks};
+use super::input::{InputDevice, Unplugged};
 use super::ppu::PPUState;
 
 /// Used to abstract over the different types of Mappers
-pub trait Mapper {
+///
+/// Mappers need to be Send, so that consoles can be moved across threads.
+pub trait Mapper: MapperClone + Send {
     fn read(&self, address: u16) -> u8;
     fn mirroring_mode(&self) -> Mirroring;
     fn write(&mut self, address: u16, value: u8);
+    /// Advances the mapper by a single CPU cycle.
+    ///
+    /// This returns true if the mapper is asserting an IRQ.
+    fn step(&mut self) -> bool {
+        false
+    }
+    /// Returns the PRG data, if the game is able to rewrite it.
+    ///
+    /// Some boards use flash memory for PRG, and store saves there.
+    fn flash(&self) -> Option<&[u8]> {
+        None
+    }
+    /// Returns the offset into PRG ROM that a CPU address at $8000 or above maps to.
+    ///
+    /// This lets the code data logger track what each byte of the ROM is used for.
+    fn prg_offset(&self, _address: u16) -> Option<usize> {
+        None
+    }
+    /// Returns the offset into CHR that a PPU address below $2000 maps to.
+    fn chr_offset(&self, _address: u16) -> Option<usize> {
+        None
+    }
+}
+
+/// Allows cloning boxed mappers, which is needed for save states.
+///
+/// This is implemented automatically for every mapper implementing Clone.
+pub trait MapperClone {
+    fn clone_box(&self) -> Box<dyn Mapper>;
+}
+
+impl<T: 'static + Mapper + Clone> MapperClone for T {
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Mapper> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
 impl Mapper {
@@ -21,36 +71,99 @@ impl Mapper {
         match cart.mapper {
             MapperID::M1 => Box::new(mapper1::Mapper1::new(cart)),
             MapperID::M2 => Box::new(mapper2::Mapper2::new(cart)),
+            MapperID::M21 | MapperID::M22 | MapperID::M23 | MapperID::M25 => {
+                Box::new(mapper21::Mapper21::new(cart))
+            }
+            MapperID::M30 => Box::new(mapper30::Mapper30::new(cart)),
         }
     }
 }
 
+/// Keeps track of how the game polled the controllers, frame by frame.
+#[derive(Clone, Default)]
+pub(crate) struct InputPolling {
+    /// How many times the ports have been read during this frame
+    reads: u32,
+    /// How many times the strobe has been turned on during this frame
+    strobes: u32,
+    /// Whether or not the strobe is currently on
+    strobe: bool,
+    /// How many times the controllers were read during the last frame
+    pub last_reads: u32,
+    /// How many times the controllers were strobed during the last frame
+    pub last_strobes: u32,
+    /// How many frames the game hasn't read the controllers in
+    pub lag_frames: u64,
+}
+
+/// Holds something the host attached to the bus, like hooks or a log.
+///
+/// These aren't part of the emulated state, so cloning a console,
+/// or taking a save state, leaves them behind.
+pub(crate) struct HostSlot<T>(pub Option<Box<T>>);
+
+impl<T> Default for HostSlot<T> {
+    fn default() -> Self {
+        HostSlot(None)
+    }
+}
+
+impl<T> Clone for HostSlot<T> {
+    fn clone(&self) -> Self {
+        HostSlot(None)
+    }
+}
+
 /// Holds cart memory
+#[derive(Clone)]
 pub(crate) struct MemoryBus {
     // Contains the mapper logic for interfacing with the cart
     // Each mapper has a different structure depending on what it
     // might need to keep track of, so we need to use dynamic dispatch.
-    pub mapper: Box<Mapper>,
+    pub mapper: Box<dyn Mapper>,
     pub apu: APUState,
     pub cpu: CPUState,
     pub ppu: PPUState,
-    // public for access by the cpu
-    pub controller1: Controller,
-    controller2: Controller,
+    // public for access by the console and ppu
+    pub port1: Box<dyn InputDevice>,
+    pub port2: Box<dyn InputDevice>,
+    // The Famicom expansion port, split into the parts read through each register
+    pub expansion1: Box<dyn InputDevice>,
+    pub expansion2: Box<dyn InputDevice>,
+    // Whether any device needs to see pixels, which is rare enough to skip otherwise
+    senses_light: bool,
+    pub polling: InputPolling,
+    pub cheats: Cheats,
+    pub hooks: HostSlot<Hooks>,
+    pub cdl: HostSlot<CodeDataLog>,
+    pub events: HostSlot<EventLog>,
+    /// The sizes of PRG ROM and CHR ROM, for creating code data logs
+    rom_sizes: (usize, usize),
     ram: [u8; 0x2000],
 }
 
 impl MemoryBus {
     /// Creates a memory bus from a c
     pub fn with_cart(cart: Cart) -> Self {
+        let chr_size = if cart.chr_ram { 0 } else { cart.chr.len() };
+        let rom_sizes = (cart.prg.len(), chr_size);
         let mapper = Mapper::with_cart(cart);
         MemoryBus {
             mapper,
             apu: APUState::new(),
             cpu: CPUState::new(),
             ppu: PPUState::new(),
-            controller1: Controller::new(),
-            controller2: Controller::new(),
+            port1: Box::new(Controller::new()),
+            port2: Box::new(Controller::new()),
+            expansion1: Box::new(Unplugged),
+            expansion2: Box::new(Unplugged),
+            senses_light: false,
+            polling: InputPolling::default(),
+            cheats: Cheats::default(),
+            hooks: HostSlot::default(),
+            cdl: HostSlot::default(),
+            events: HostSlot::default(),
+            rom_sizes,
             ram: [0; 0x2000],
         }
     }
@@ -64,7 +177,204 @@ impl MemoryBus {
         self.ppu = PPUState::new();
     }
 
+    /// Returns the 2KB of work RAM, without its mirrors
+    pub fn ram(&self) -> &[u8] {
+        &self.ram[..0x800]
+    }
+
+    /// Returns the 8KB of cartridge RAM, mapped at $6000
+    pub fn sram(&self) -> Vec<u8> {
+        (0x6000..0x8000).map(|a| self.mapper.read(a)).collect()
+    }
+
+    /// Reads an address without any side effects.
+    ///
+    /// IO registers can't be read without side effects, so they read as 0.
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            a if a < 0x2000 => self.ram[(a % 0x800) as usize],
+            a if a >= 0x6000 => self.mapper.read(a),
+            _ => 0,
+        }
+    }
+
+    /// Writes to work RAM or cartridge RAM, ignoring other addresses.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            a if a < 0x2000 => self.ram[(a % 0x800) as usize] = value,
+            0x6000..=0x7FFF => self.mapper.write(address, value),
+            _ => {}
+        }
+    }
+
+    /// Returns an empty code data log, sized for the cart.
+    pub fn new_cdl(&self) -> CodeDataLog {
+        CodeDataLog::new(self.rom_sizes.0, self.rom_sizes.1)
+    }
+
+    /// Changes the flags that CPU reads of PRG are logged with, returning the old ones.
+    pub fn set_cdl_access(&mut self, access: u8) -> u8 {
+        match &mut self.cdl.0 {
+            Some(cdl) => std::mem::replace(&mut cdl.access, access),
+            None => 0,
+        }
+    }
+
+    /// Logs an access to the PRG ROM behind an address, if logging is on.
+    pub fn log_prg(&mut self, address: u16, flags: u8) {
+        if let Some(cdl) = &mut self.cdl.0 {
+            if address >= 0x8000 {
+                if let Some(offset) = self.mapper.prg_offset(address) {
+                    cdl.log_prg(offset, address, flags);
+                }
+            }
+        }
+    }
+
+    /// Logs an access to the CHR behind a PPU address, if logging is on.
+    pub fn log_chr(&mut self, address: u16, flags: u8) {
+        if let Some(cdl) = &mut self.cdl.0 {
+            if address < 0x2000 {
+                if let Some(offset) = self.mapper.chr_offset(address) {
+                    cdl.log_chr(offset, flags);
+                }
+            }
+        }
+    }
+
+    /// Logs a CPU read, before it happens, since reading $2007 moves the PPU address.
+    fn log_read(&mut self, address: u16) {
+        let access = match &self.cdl.0 {
+            Some(cdl) => cdl.access,
+            None => return,
+        };
+        match address {
+            a if a >= 0x8000 => self.log_prg(a, access),
+            0x2000..=0x3FFF if address % 8 == 7 => {
+                let v = self.ppu.v % 0x4000;
+                self.log_chr(v, CHR_READ);
+            }
+            _ => {}
+        }
+    }
+
+    /// Logs the PPU fetching a pattern while rendering.
+    #[inline]
+    pub fn log_pattern_fetch(&mut self, address: u16) {
+        if self.cdl.0.is_some() {
+            self.log_chr(address, CHR_RENDERED);
+        }
+    }
+
+    /// Reads a byte of audio samples for the DMC.
+    pub fn read_sample(&mut self, address: u16) -> u8 {
+        let access = self.set_cdl_access(PRG_PCM);
+        let value = self.cpu_read(address);
+        self.set_cdl_access(access);
+        value
+    }
+
+    /// Runs some hooks with access to the rest of the bus.
+    ///
+    /// If no hooks are registered, this returns `default` right away.
+    #[inline]
+    pub fn with_hooks<T, F>(&mut self, default: T, f: F) -> T
+    where
+        F: FnOnce(&mut Hooks, &mut HookContext) -> T,
+    {
+        // The hooks are taken out while they run, so they can borrow the bus
+        match self.hooks.0.take() {
+            None => default,
+            Some(mut hooks) => {
+                let res = f(&mut hooks, &mut HookContext::new(self));
+                self.hooks.0 = Some(hooks);
+                res
+            }
+        }
+    }
+
+    /// Advances the mapper by a CPU cycle, forwarding any IRQ it raises
+    pub fn step_mapper(&mut self) {
+        let irq = self.mapper.step();
+        if irq {
+            self.cpu.set_irq();
+        }
+        if let Some(events) = &mut self.events.0 {
+            events.mapper_irq(irq);
+        }
+    }
+
+    /// Calls a function on every input device, in both ports and the expansion port.
+    pub fn for_each_input<F: FnMut(&mut dyn InputDevice)>(&mut self, mut f: F) {
+        f(&mut *self.port1);
+        f(&mut *self.port2);
+        f(&mut *self.expansion1);
+        f(&mut *self.expansion2);
+    }
+
+    /// Returns true if any input device needs to see the pixels being drawn.
+    pub fn senses_light(&self) -> bool {
+        self.senses_light
+    }
+
+    /// Checks which devices sense light again, after one has been plugged in.
+    pub fn update_light_sensing(&mut self) {
+        let mut senses_light = false;
+        self.for_each_input(|device| senses_light |= device.senses_light());
+        self.senses_light = senses_light;
+    }
+
+    /// Records how the controllers were used during the frame that just ended.
+    ///
+    /// A frame where the controllers were never read is a lag frame.
+    pub fn end_frame(&mut self) {
+        let polling = &mut self.polling;
+        polling.last_reads = polling.reads;
+        polling.last_strobes = polling.strobes;
+        if polling.reads == 0 {
+            polling.lag_frames += 1;
+        }
+        polling.reads = 0;
+        polling.strobes = 0;
+        self.for_each_input(|device| device.end_frame());
+        self.apply_freezes();
+        self.with_hooks((), |hooks, ctx| hooks.frame_end(ctx));
+    }
+
+    /// Writes the values of freeze codes to RAM and SRAM.
+    fn apply_freezes(&mut self) {
+        for &(address, value) in self.cheats.freezes() {
+            match address {
+                a if a < 0x2000 => self.ram[(a % 0x800) as usize] = value,
+                0x6000..=0x7FFF => self.mapper.write(address, value),
+                _ => {}
+            }
+        }
+    }
+
     pub fn cpu_read(&mut self, address: u16) -> u8 {
+        if self.cdl.0.is_some() {
+            self.log_read(address);
+        }
+        let value = self.bus_read(address);
+        // Checking first avoids taking the hooks out on every access
+        if self.hooks.0.as_ref().is_some_and(|h| h.has_reads()) {
+            self.with_hooks(value, |hooks, ctx| hooks.read(ctx, address, value))
+        } else {
+            value
+        }
+    }
+
+    pub fn cpu_write(&mut self, address: u16, value: u8) {
+        let value = if self.hooks.0.as_ref().is_some_and(|h| h.has_writes()) {
+            self.with_hooks(value, |hooks, ctx| hooks.write(ctx, address, value))
+        } else {
+            value
+        };
+        self.bus_write(address, value);
+    }
+
+    fn bus_read(&mut self, address: u16) -> u8 {
         match address {
             a if a < 0x2000 => self.ram[(a % 0x800) as usize],
             a if a < 0x4000 => {
@@ -73,16 +383,51 @@ impl MemoryBus {
             }
             0x4014 => self.ppu.read_register(&*self.mapper, 0x4014),
             0x4015 => self.apu.read_register(address),
-            0x4016 => self.controller1.read(),
-            0x4017 => self.controller2.read(),
-            a if a >= 0x6000 => self.mapper.read(address),
+            0x4016 => {
+                self.polling.reads += 1;
+                (self.port1.read() | self.expansion1.read()) & 0x1F
+            }
+            0x4017 => {
+                self.polling.reads += 1;
+                (self.port2.read() | self.expansion2.read()) & 0x1F
+            }
+            a if a >= 0x6000 => {
+                let value = self.mapper.read(address);
+                if self.cheats.has_patches() {
+                    self.cheats.patch(address, value)
+                } else {
+                    value
+                }
+            }
             a => {
                 panic!("Unhandled CPU read at {:X}", a);
             }
         }
     }
 
-    pub fn cpu_write(&mut self, address: u16, value: u8) {
+    /// Records writes to the PPU and to the mapper in the event log
+    fn log_write(&mut self, address: u16, value: u8) {
+        let kind = match address {
+            0x2000..=0x3FFF => EventKind::PpuWrite {
+                register: 0x2000 + address % 8,
+                value,
+            },
+            0x4014 => EventKind::PpuWrite {
+                register: address,
+                value,
+            },
+            0x8000..=0xFFFF => EventKind::MapperWrite { address, value },
+            _ => return,
+        };
+        if let Some(events) = &mut self.events.0 {
+            events.record(kind);
+        }
+    }
+
+    fn bus_write(&mut self, address: u16, value: u8) {
+        if self.events.0.is_some() {
+            self.log_write(address, value);
+        }
         match address {
             a if a < 0x2000 => self.ram[(a % 0x800) as usize] = value,
             a if a < 0x4000 => {
@@ -96,8 +441,12 @@ impl MemoryBus {
             }
             0x4015 => self.apu.write_register(address, value),
             0x4016 => {
-                self.controller1.write(value);
-                self.controller2.write(value);
+                let strobe = value & 1 == 1;
+                if strobe && !self.polling.strobe {
+                    self.polling.strobes += 1;
+                }
+                self.polling.strobe = strobe;
+                self.for_each_input(|device| device.write(value));
             }
             0x4017 => self.apu.write_register(address, value),
             a if a >= 0x6000 => self.mapper.write(address, value),
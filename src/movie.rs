@@ -0,0 +1,283 @@
+//! Recording and playing back the input of a game, frame by frame.
+//!
+//! Movies can be imported from, and exported to, the `.fm2` text format
+//! used by FCEUX, which is how most tool assisted speedruns are shared.
+//!
+//! More info: http://fceux.com/web/help/fm2.html
+use crate::console::Console;
+use crate::controller::ButtonState;
+use crate::ports::{AudioDevice, VideoDevice};
+
+/// Represents the possible errors when reading an FM2 movie
+#[derive(Clone, Debug, PartialEq)]
+pub enum MovieError {
+    /// The movie has no version line, so it isn't an FM2 file
+    UnrecognisedFormat,
+    /// The input log uses the binary encoding, which isn't supported
+    BinaryInput,
+    /// The movie starts from a save state instead of power on
+    StartsFromSaveState,
+    /// A port uses a device other than a gamepad, like a zapper
+    UnsupportedPort(String),
+    /// A line of the input log couldn't be parsed, with its line number
+    InvalidInput(usize),
+}
+
+/// The input for a single frame of a movie.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MovieFrame {
+    /// The buttons held on the first controller
+    pub port1: ButtonState,
+    /// The buttons held on the second controller
+    pub port2: ButtonState,
+    /// Whether the reset button is pressed before this frame
+    pub reset: bool,
+    /// Whether the console is power cycled before this frame
+    pub power: bool,
+}
+
+impl MovieFrame {
+    /// Creates a frame where only the first controller is used.
+    pub fn new(port1: ButtonState) -> Self {
+        MovieFrame {
+            port1,
+            ..MovieFrame::default()
+        }
+    }
+
+    /// Applies the input of this frame to a console.
+    ///
+    /// Since resetting already clears RAM, power cycles are
+    /// treated the same way as resets.
+    pub fn apply(&self, console: &mut Console) {
+        if self.reset || self.power {
+            console.reset();
+        }
+        console.update_controller(self.port1);
+        console.update_controller2(self.port2);
+    }
+}
+
+// FM2 stores the buttons in this order, from left to right
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+fn buttons_to_fm2(buttons: ButtonState, out: &mut String) {
+    let held = [
+        buttons.right,
+        buttons.left,
+        buttons.down,
+        buttons.up,
+        buttons.start,
+        buttons.select,
+        buttons.b,
+        buttons.a,
+    ];
+    for (&letter, &held) in FM2_BUTTONS.iter().zip(held.iter()) {
+        out.push(if held { letter as char } else { '.' });
+    }
+}
+
+fn buttons_from_fm2(field: &str) -> Option<ButtonState> {
+    let bytes = field.as_bytes();
+    if bytes.len() != 8 {
+        return None;
+    }
+    // Anything other than a space or a dot counts as being held
+    let held = |i: usize| bytes[i] != b'.' && bytes[i] != b' ';
+    Some(ButtonState {
+        right: held(0),
+        left: held(1),
+        down: held(2),
+        up: held(3),
+        start: held(4),
+        select: held(5),
+        b: held(6),
+        a: held(7),
+    })
+}
+
+/// A recording of the input for a game, frame by frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Movie {
+    /// The header lines of the movie, as key value pairs.
+    ///
+    /// Keys describing the input format, like the ports, are handled
+    /// when importing and exporting, and don't appear here.
+    pub header: Vec<(String, String)>,
+    /// The input for each frame, starting from power on
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// Creates an empty movie.
+    pub fn new() -> Self {
+        Movie::default()
+    }
+
+    /// Returns the value of a header key, like `romFilename`, if present.
+    pub fn header_value(&self, key: &str) -> Option<&str> {
+        self.header
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Applies a frame of input to a console, and advances it to the next frame,
+    /// adding that frame to the end of the movie.
+    pub fn record<A, V>(
+        &mut self,
+        frame: MovieFrame,
+        console: &mut Console,
+        audio: &mut A,
+        video: &mut V,
+    ) where
+        A: AudioDevice,
+        V: VideoDevice,
+    {
+        frame.apply(console);
+        console.step_frame(audio, video);
+        self.frames.push(frame);
+    }
+
+    /// Plays back a single frame of the movie, advancing the console by a frame.
+    ///
+    /// This returns false without doing anything if the movie has no such frame.
+    pub fn play_frame<A, V>(
+        &self,
+        index: usize,
+        console: &mut Console,
+        audio: &mut A,
+        video: &mut V,
+    ) -> bool
+    where
+        A: AudioDevice,
+        V: VideoDevice,
+    {
+        match self.frames.get(index) {
+            Some(frame) => {
+                frame.apply(console);
+                console.step_frame(audio, video);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Plays back the entire movie on a console.
+    ///
+    /// The console should have just been powered on, for the movie to sync.
+    pub fn play<A, V>(&self, console: &mut Console, audio: &mut A, video: &mut V)
+    where
+        A: AudioDevice,
+        V: VideoDevice,
+    {
+        for frame in &self.frames {
+            frame.apply(console);
+            console.step_frame(audio, video);
+        }
+    }
+
+    /// Parses a movie in FCEUX's text based FM2 format.
+    pub fn from_fm2(text: &str) -> Result<Movie, MovieError> {
+        let mut movie = Movie::new();
+        let mut has_version = false;
+        let mut ports = [true, true];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                movie
+                    .frames
+                    .push(Movie::parse_fm2_frame(line, ports, i + 1)?);
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find(' ') {
+                Some(space) => (&line[..space], &line[space + 1..]),
+                None => (line, ""),
+            };
+            match key {
+                "version" => has_version = true,
+                "binary" if value == "true" || value == "1" => return Err(MovieError::BinaryInput),
+                "binary" => {}
+                "savestate" => return Err(MovieError::StartsFromSaveState),
+                "fourscore" if value == "1" => {
+                    return Err(MovieError::UnsupportedPort("fourscore".to_string()))
+                }
+                "fourscore" => {}
+                "port0" | "port1" => {
+                    let port = if key == "port0" { 0 } else { 1 };
+                    match value {
+                        "0" => ports[port] = false,
+                        "1" => ports[port] = true,
+                        _ => return Err(MovieError::UnsupportedPort(line.to_string())),
+                    }
+                }
+                "port2" if value != "0" => {
+                    return Err(MovieError::UnsupportedPort(line.to_string()))
+                }
+                "port2" => {}
+                _ => movie.header.push((key.to_string(), value.to_string())),
+            }
+        }
+        if !has_version {
+            return Err(MovieError::UnrecognisedFormat);
+        }
+        Ok(movie)
+    }
+
+    fn parse_fm2_frame(
+        line: &str,
+        ports: [bool; 2],
+        line_number: usize,
+    ) -> Result<MovieFrame, MovieError> {
+        let err = MovieError::InvalidInput(line_number);
+        // A line looks like "|commands|port0|port1|port2|"
+        let mut fields = line[1..].split('|');
+        let commands: u8 = fields
+            .next()
+            .and_then(|c| c.trim().parse().ok())
+            .ok_or_else(|| err.clone())?;
+        let mut frame = MovieFrame {
+            reset: commands & 1 != 0,
+            power: commands & 2 != 0,
+            ..MovieFrame::default()
+        };
+        for (port, &used) in ports.iter().enumerate() {
+            let field = fields.next().ok_or_else(|| err.clone())?;
+            if !used {
+                continue;
+            }
+            let buttons = buttons_from_fm2(field).ok_or_else(|| err.clone())?;
+            if port == 0 {
+                frame.port1 = buttons;
+            } else {
+                frame.port2 = buttons;
+            }
+        }
+        Ok(frame)
+    }
+
+    /// Writes this movie in FCEUX's text based FM2 format.
+    ///
+    /// Both ports are always written as gamepads.
+    pub fn to_fm2(&self) -> String {
+        let mut out = String::from("version 3\n");
+        for (key, value) in &self.header {
+            if key != "version" {
+                out.push_str(&format!("{} {}\n", key, value));
+            }
+        }
+        out.push_str("port0 1\nport1 1\nport2 0\n");
+        for frame in &self.frames {
+            let commands = u8::from(frame.reset) | (u8::from(frame.power) << 1);
+            out.push_str(&format!("|{}|", commands));
+            buttons_to_fm2(frame.port1, &mut out);
+            out.push('|');
+            buttons_to_fm2(frame.port2, &mut out);
+            out.push_str("||\n");
+        }
+        out
+    }
+}
@@ -0,0 +1,109 @@
+use crate::controller::ButtonState;
+use crate::input::InputDevice;
+
+/// The different kinds of adapters allowing four players
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MultitapMode {
+    /// The NES Four Score, where every player is read through D0
+    FourScore,
+    /// The Famicom Hori 4 Players Adapter, where the third and fourth
+    /// players are read through D1
+    Hori,
+}
+
+/// The signatures, in the order they're read, for each port
+const FOUR_SCORE_SIGNATURES: [[bool; 8]; 2] = [
+    [false, false, false, true, false, false, false, false],
+    [false, false, true, false, false, false, false, false],
+];
+const HORI_SIGNATURES: [[bool; 8]; 2] = [
+    [false, false, false, false, false, true, false, false],
+    [false, false, false, false, true, false, false, false],
+];
+
+/// Represents one side of a four player adapter.
+///
+/// Each port gets its own half of the adapter, with the first port
+/// handling players 1 and 3, and the second players 2 and 4.
+/// After the buttons of both players, a signature is sent, which
+/// games use to detect the adapter.
+///
+/// More info: https://wiki.nesdev.com/w/index.php/Four_player_adapters
+#[derive(Clone, Debug)]
+pub struct Multitap {
+    mode: MultitapMode,
+    /// The bits sent through D0, in order
+    d0: [bool; 24],
+    /// The bits sent through D1, in order
+    d1: [bool; 24],
+    /// What bit is currently being read
+    index: u8,
+    strobe: bool,
+}
+
+impl Multitap {
+    /// Creates half of an adapter, for the first port if `port` is 0,
+    /// and the second port otherwise.
+    pub fn new(mode: MultitapMode, port: usize) -> Self {
+        let port = port.min(1);
+        let mut multitap = Multitap {
+            mode,
+            d0: [false; 24],
+            d1: [false; 24],
+            index: 0,
+            strobe: false,
+        };
+        match mode {
+            MultitapMode::FourScore => {
+                multitap.d0[16..].copy_from_slice(&FOUR_SCORE_SIGNATURES[port]);
+            }
+            MultitapMode::Hori => {
+                multitap.d1[8..16].copy_from_slice(&HORI_SIGNATURES[port]);
+                // Nothing is connected after the signature
+                for bit in &mut multitap.d0[8..] {
+                    *bit = true;
+                }
+                for bit in &mut multitap.d1[16..] {
+                    *bit = true;
+                }
+            }
+        }
+        multitap
+    }
+
+    /// Updates the buttons of both players connected to this side.
+    ///
+    /// The first player is 1 or 2, and the second is 3 or 4.
+    pub fn set_buttons(&mut self, first: ButtonState, second: ButtonState) {
+        match self.mode {
+            MultitapMode::FourScore => {
+                self.d0[..8].copy_from_slice(&first.bits());
+                self.d0[8..16].copy_from_slice(&second.bits());
+            }
+            MultitapMode::Hori => {
+                self.d0[..8].copy_from_slice(&first.bits());
+                self.d1[..8].copy_from_slice(&second.bits());
+            }
+        }
+    }
+}
+
+impl InputDevice for Multitap {
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 == 1;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        let index = self.index as usize;
+        // Once every bit has been read, the adapter only sends 1s
+        let bit = |bits: &[bool; 24]| u8::from(*bits.get(index).unwrap_or(&true));
+        let res = bit(&self.d0) | (bit(&self.d1) << 1);
+        if !self.strobe {
+            self.index = self.index.saturating_add(1);
+        }
+        res
+    }
+}
@@ -0,0 +1,274 @@
+//! The colors the PPU outputs, including the effect of the emphasis bits.
+//!
+//! The NES doesn't output RGB colors, but an NTSC signal, so each emulator
+//! has its own idea of what the 64 colors look like. A palette holds 512
+//! colors: the 64 colors for each of the 8 combinations of the emphasis
+//! bits of $2001, in the same layout as 512 entry `.pal` files.
+//!
+//! More info: https://www.nesdev.org/wiki/PPU_palettes
+use std::f32::consts::PI;
+
+/// Represents the possible errors when loading a `.pal` file
+#[derive(Clone, Debug, PartialEq)]
+pub enum PaletteError {
+    /// The file should contain 64 or 512 colors, of 3 bytes each
+    InvalidSize(usize),
+}
+
+/// The palettes built into the emulator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BuiltinPalette {
+    /// The colors of the 2C02, the PPU in NTSC consoles, used by default
+    Ppu2C02,
+    /// The RGB PPU used in arcade boards and Famicom TVs
+    Ppu2C03,
+    /// The RGB PPU used in some Vs. System boards.
+    ///
+    /// This has the same colors as the 2C03.
+    Ppu2C05,
+    /// The default palette of FCEUX
+    Fceux,
+    /// A palette decoded from the NTSC signal of the 2C02, with even hues
+    Smooth,
+}
+
+/// How much the emphasis bits dim the other channels, on the 2C02
+const ATTENUATION: f32 = 0.746;
+
+/// The colors of the 2C02, as ARGB
+const PPU_2C02: [u32; 64] = [
+    0xFF75_7575,
+    0xFF27_1B8F,
+    0xFF00_00AB,
+    0xFF47_009F,
+    0xFF8F_0077,
+    0xFFAB_0013,
+    0xFFA7_0000,
+    0xFF7F_0B00,
+    0xFF43_2F00,
+    0xFF00_4700,
+    0xFF00_5100,
+    0xFF00_3F17,
+    0xFF1B_3F5F,
+    0xFF00_0000,
+    0xFF00_0000,
+    0xFF00_0000,
+    0xFFBC_BCBC,
+    0xFF00_73EF,
+    0xFF23_3BEF,
+    0xFF83_00F3,
+    0xFFBF_00BF,
+    0xFFE7_005B,
+    0xFFDB_2B00,
+    0xFFCB_4F0F,
+    0xFF8B_7300,
+    0xFF00_9700,
+    0xFF00_AB00,
+    0xFF00_933B,
+    0xFF00_838B,
+    0xFF00_0000,
+    0xFF00_0000,
+    0xFF00_0000,
+    0xFFFF_FFFF,
+    0xFF3F_BFFF,
+    0xFF5F_97FF,
+    0xFFA7_8BFD,
+    0xFFF7_7BFF,
+    0xFFFF_77B7,
+    0xFFFF_7763,
+    0xFFFF_9B3B,
+    0xFFF3_BF3F,
+    0xFF83_D313,
+    0xFF4F_DF4B,
+    0xFF58_F898,
+    0xFF00_EBDB,
+    0xFF00_0000,
+    0xFF00_0000,
+    0xFF00_0000,
+    0xFFFF_FFFF,
+    0xFFAB_E7FF,
+    0xFFC7_D7FF,
+    0xFFD7_CBFF,
+    0xFFFF_C7FF,
+    0xFFFF_C7DB,
+    0xFFFF_BFB3,
+    0xFFFF_DBAB,
+    0xFFFF_E7A3,
+    0xFFE3_FFA3,
+    0xFFAB_F3BF,
+    0xFFB3_FFCF,
+    0xFF9F_FFF3,
+    0xFF00_0000,
+    0xFF00_0000,
+    0xFF00_0000,
+];
+
+/// The colors of the RGB PPUs, with 3 bits for each of red, green and blue
+const PPU_2C03: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022,
+    0o000, 0o000, 0o000, 0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140,
+    0o040, 0o053, 0o044, 0o000, 0o000, 0o000, 0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740,
+    0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000, 0o777, 0o567, 0o657, 0o757,
+    0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+/// The colors of FCEUX's default palette, as RGB
+const FCEUX: [u32; 64] = [
+    0x74_7474, 0x24_188C, 0x00_00A8, 0x44_009C, 0x8C_0074, 0xA8_0010, 0xA4_0000, 0x7C_0800,
+    0x40_2C00, 0x00_4400, 0x00_5000, 0x00_3C14, 0x18_3C5C, 0x00_0000, 0x00_0000, 0x00_0000,
+    0xBC_BCBC, 0x00_70EC, 0x20_38EC, 0x80_00F0, 0xBC_00BC, 0xE4_0058, 0xD8_2800, 0xC8_4C0C,
+    0x88_7000, 0x00_9400, 0x00_A800, 0x00_9038, 0x00_8088, 0x00_0000, 0x00_0000, 0x00_0000,
+    0xFC_FCFC, 0x3C_BCFC, 0x5C_94FC, 0xCC_88FC, 0xF4_78FC, 0xFC_74B4, 0xFC_7460, 0xFC_9838,
+    0xF0_BC3C, 0x80_D010, 0x4C_DC48, 0x58_F898, 0x00_E8D8, 0x78_7878, 0x00_0000, 0x00_0000,
+    0xFC_FCFC, 0xA8_E4FC, 0xC4_D4FC, 0xD4_C8FC, 0xFC_C4FC, 0xFC_C4D8, 0xFC_BCB0, 0xFC_D8A8,
+    0xFC_E4A0, 0xE0_FCA0, 0xA8_F0BC, 0xB0_FCCC, 0x9C_FCF0, 0xC4_C4C4, 0x00_0000, 0x00_0000,
+];
+
+fn argb(r: u8, g: u8, b: u8) -> u32 {
+    0xFF00_0000 | (u32::from(r) << 16) | (u32::from(g) << 8) | u32::from(b)
+}
+
+/// Builds the 64 colors of a palette, one index at a time
+fn build<F: Fn(usize) -> u32>(f: F) -> [u32; 64] {
+    let mut colors = [0; 64];
+    for (i, color) in colors.iter_mut().enumerate() {
+        *color = f(i);
+    }
+    colors
+}
+
+fn channels(argb: u32) -> [u8; 3] {
+    [(argb >> 16) as u8, (argb >> 8) as u8, argb as u8]
+}
+
+/// Decodes one of the 64 colors from the NTSC signal the 2C02 generates.
+///
+/// The signal is a square wave between two voltages, in one of 12 phases.
+fn ntsc_color(index: usize) -> u32 {
+    const LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+    const HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+    const BLACK: f32 = 0.518;
+    const WHITE: f32 = 1.962;
+    let hue = index & 0x0F;
+    let level = if hue < 0x0E { (index >> 4) & 3 } else { 1 };
+    let low = if hue == 0 { HIGH[level] } else { LOW[level] };
+    let high = if hue < 0x0D { HIGH[level] } else { LOW[level] };
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for phase in 0..12 {
+        // Phases are measured against the color burst, which has the phase of hue 8
+        let in_phase = (hue + phase + 8) % 12 < 6;
+        let signal = if in_phase { high } else { low };
+        let signal = (signal - BLACK) / (WHITE - BLACK) / 12.0;
+        let angle = PI * phase as f32 / 6.0;
+        y += signal;
+        i += signal * angle.cos();
+        q += signal * angle.sin();
+    }
+    let to_byte = |v: f32| (v.clamp(0.0, 1.0).powf(2.2 / 1.8) * 255.0).round() as u8;
+    argb(
+        to_byte(y + 0.946_882 * i + 0.623_557 * q),
+        to_byte(y - 0.274_788 * i - 0.635_691 * q),
+        to_byte(y - 1.108_545 * i + 1.709_007 * q),
+    )
+}
+
+/// The 512 colors the PPU can output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<u32>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::builtin(BuiltinPalette::Ppu2C02)
+    }
+}
+
+impl Palette {
+    /// Creates one of the palettes built into the emulator.
+    pub fn builtin(palette: BuiltinPalette) -> Self {
+        match palette {
+            BuiltinPalette::Ppu2C02 => Palette::from_colors(&PPU_2C02),
+            BuiltinPalette::Ppu2C03 | BuiltinPalette::Ppu2C05 => {
+                let scale = |bits: u16| ((bits & 7) * 255 / 7) as u8;
+                Palette::rgb_emphasis(&build(|i| {
+                    let c = PPU_2C03[i];
+                    argb(scale(c >> 6), scale(c >> 3), scale(c))
+                }))
+            }
+            BuiltinPalette::Fceux => Palette::from_colors(&build(|i| 0xFF00_0000 | FCEUX[i])),
+            BuiltinPalette::Smooth => Palette::from_colors(&build(ntsc_color)),
+        }
+    }
+
+    /// Creates a palette from 64 colors, in ARGB format.
+    ///
+    /// The emphasis bits dim the channels that aren't emphasized, like on
+    /// the 2C02. This doesn't affect the blacks in columns $E and $F.
+    pub fn from_colors(base: &[u32; 64]) -> Self {
+        let mut colors = Vec::with_capacity(512);
+        for emphasis in 0..8 {
+            for (index, &color) in base.iter().enumerate() {
+                if emphasis == 0 || index & 0x0F >= 0x0E {
+                    colors.push(color);
+                    continue;
+                }
+                let mut rgb = channels(color);
+                for (channel, value) in rgb.iter_mut().enumerate() {
+                    if emphasis & (1 << channel) == 0 {
+                        *value = (f32::from(*value) * ATTENUATION).round() as u8;
+                    }
+                }
+                colors.push(argb(rgb[0], rgb[1], rgb[2]));
+            }
+        }
+        Palette { colors }
+    }
+
+    /// Creates a palette for RGB PPUs, where emphasis maxes out channels instead
+    fn rgb_emphasis(base: &[u32; 64]) -> Self {
+        let mut colors = Vec::with_capacity(512);
+        for emphasis in 0..8 {
+            for &color in base {
+                let mut rgb = channels(color);
+                for (channel, value) in rgb.iter_mut().enumerate() {
+                    if emphasis & (1 << channel) != 0 {
+                        *value = 0xFF;
+                    }
+                }
+                colors.push(argb(rgb[0], rgb[1], rgb[2]));
+            }
+        }
+        Palette { colors }
+    }
+
+    /// Loads a `.pal` file, containing 64 or 512 colors as RGB triplets.
+    ///
+    /// Files with 64 colors get the same emphasis as `from_colors`.
+    pub fn from_pal(bytes: &[u8]) -> Result<Self, PaletteError> {
+        if bytes.len() != 64 * 3 && bytes.len() != 512 * 3 {
+            return Err(PaletteError::InvalidSize(bytes.len()));
+        }
+        let color = |i: usize| argb(bytes[i * 3], bytes[i * 3 + 1], bytes[i * 3 + 2]);
+        if bytes.len() == 64 * 3 {
+            Ok(Palette::from_colors(&build(color)))
+        } else {
+            Ok(Palette {
+                colors: (0..512).map(color).collect(),
+            })
+        }
+    }
+
+    /// Returns the 512 colors, with 64 colors for each emphasis.
+    pub fn colors(&self) -> &[u32] {
+        &self.colors
+    }
+
+    /// Returns the color of an index, with the emphasis bits of $2001.
+    ///
+    /// The emphasis bits are red, green and blue, starting from the lowest bit.
+    pub fn color(&self, index: u8, emphasis: u8) -> u32 {
+        let emphasis = usize::from(emphasis & 7);
+        self.colors[emphasis * 64 + usize::from(index & 0x3F)]
+    }
+}
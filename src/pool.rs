@@ -0,0 +1,268 @@
+//! Running many consoles in parallel.
+//!
+//! This is useful for things like reinforcement learning, where many
+//! copies of a game are played at the same time.
+use crate::console::{Console, SaveState};
+use crate::controller::ButtonState;
+use crate::ports::{AudioDevice, PixelBuffer, VideoDevice, NES_HEIGHT, NES_WIDTH};
+use std::mem;
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+const FRAME_PIXELS: usize = NES_WIDTH * NES_HEIGHT;
+
+/// Copies the frame produced by a console into a slice of a larger buffer.
+struct FrameSlice<'a>(&'a mut [u32]);
+
+impl VideoDevice for FrameSlice<'_> {
+    fn blit_pixels(&mut self, pixels: &PixelBuffer) {
+        self.0.copy_from_slice(pixels.as_ref());
+    }
+}
+
+/// Collects the samples produced by a console.
+struct SampleVec<'a>(&'a mut Vec<f32>);
+
+impl AudioDevice for SampleVec<'_> {
+    fn push_sample(&mut self, sample: f32) {
+        self.0.push(sample);
+    }
+}
+
+/// The output of stepping every console in a pool by a frame.
+///
+/// Frames and audio are stored in contiguous buffers, in the same
+/// order as the consoles in the pool.
+#[derive(Clone, Debug, Default)]
+pub struct FrameBatch {
+    /// The frame of each console, one after the other, in ARGB format
+    pub pixels: Vec<u32>,
+    /// The audio samples of each console, one after the other
+    pub samples: Vec<f32>,
+    /// The range of `samples` produced by each console
+    pub sample_ranges: Vec<Range<usize>>,
+}
+
+impl FrameBatch {
+    /// Returns the frame produced by a given console.
+    pub fn frame(&self, console: usize) -> &[u32] {
+        let start = console * FRAME_PIXELS;
+        &self.pixels[start..start + FRAME_PIXELS]
+    }
+
+    /// Returns the audio samples produced by a given console.
+    pub fn audio(&self, console: usize) -> &[f32] {
+        &self.samples[self.sample_ranges[console].clone()]
+    }
+}
+
+/// A chunk of consoles to step by a frame, along with their output.
+///
+/// Jobs get sent to a worker and back every frame, which lets them
+/// reuse their buffers instead of allocating new ones.
+#[derive(Default)]
+struct Job {
+    consoles: Vec<Console>,
+    buttons: Vec<ButtonState>,
+    pixels: Vec<u32>,
+    audio: Vec<Vec<f32>>,
+}
+
+impl Job {
+    fn run(&mut self) {
+        self.pixels.resize(self.consoles.len() * FRAME_PIXELS, 0);
+        self.audio.resize_with(self.consoles.len(), Vec::new);
+        let frames = self.pixels.chunks_mut(FRAME_PIXELS);
+        let work = self.consoles.iter_mut().zip(&self.buttons);
+        for (((console, &buttons), frame), samples) in work.zip(frames).zip(&mut self.audio) {
+            samples.clear();
+            console.update_controller(buttons);
+            console.step_frame(&mut SampleVec(samples), &mut FrameSlice(frame));
+        }
+    }
+}
+
+/// A thread running jobs for a pool, until the pool gets dropped.
+struct Worker {
+    jobs: Sender<Job>,
+    done: Receiver<Job>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    fn spawn() -> Self {
+        let (jobs, incoming) = mpsc::channel::<Job>();
+        let (finished, done) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            for mut job in incoming {
+                job.run();
+                if finished.send(job).is_err() {
+                    return;
+                }
+            }
+        });
+        Worker {
+            jobs,
+            done,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Closing the channel of jobs is what makes the thread stop
+        drop(mem::replace(&mut self.jobs, mpsc::channel().0));
+        if let Some(handle) = self.handle.take() {
+            // A worker that panicked already made `step_frame` panic
+            let _ = handle.join();
+        }
+    }
+}
+
+/// A collection of consoles, stepped together across multiple threads.
+///
+/// Each call to `step_frame` splits the consoles into one chunk per thread,
+/// so the work is spread evenly when all consoles run similar games.
+/// The threads are started the first time they're needed, and stay around
+/// until the pool is dropped, with the chunks sent to them through channels.
+pub struct ConsolePool {
+    consoles: Vec<Console>,
+    threads: usize,
+    workers: Vec<Worker>,
+    // Reused between frames to avoid allocating
+    jobs: Vec<Job>,
+    batch: FrameBatch,
+}
+
+impl ConsolePool {
+    /// Creates a new pool, using as many threads as the machine has cores.
+    pub fn new(consoles: Vec<Console>) -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let count = consoles.len();
+        ConsolePool {
+            consoles,
+            threads,
+            workers: Vec::new(),
+            jobs: Vec::new(),
+            batch: FrameBatch {
+                pixels: vec![0; count * FRAME_PIXELS],
+                samples: Vec::new(),
+                sample_ranges: vec![0..0; count],
+            },
+        }
+    }
+
+    /// Sets the number of threads used to step the consoles.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self.workers.truncate(self.threads);
+        self.jobs.truncate(self.threads);
+        self
+    }
+
+    /// Returns how many consoles are in this pool.
+    pub fn len(&self) -> usize {
+        self.consoles.len()
+    }
+
+    /// Returns true if this pool contains no consoles.
+    pub fn is_empty(&self) -> bool {
+        self.consoles.is_empty()
+    }
+
+    /// Returns the consoles in this pool.
+    pub fn consoles(&self) -> &[Console] {
+        &self.consoles
+    }
+
+    /// Returns the consoles in this pool, allowing them to be modified.
+    pub fn consoles_mut(&mut self) -> &mut [Console] {
+        &mut self.consoles
+    }
+
+    /// Advances every console until its next frame.
+    ///
+    /// Each console gets the buttons at the same position in `buttons`,
+    /// which must contain exactly one state per console.
+    pub fn step_frame(&mut self, buttons: &[ButtonState]) -> &FrameBatch {
+        assert_eq!(
+            buttons.len(),
+            self.consoles.len(),
+            "ConsolePool needs one ButtonState per console"
+        );
+        if self.consoles.is_empty() {
+            return &self.batch;
+        }
+        let per_thread = self.consoles.len().div_ceil(self.threads);
+        let chunks = self.consoles.len().div_ceil(per_thread);
+        while self.workers.len() < chunks {
+            self.workers.push(Worker::spawn());
+        }
+        self.jobs
+            .resize_with(chunks.max(self.jobs.len()), Job::default);
+        // Taking the chunks from the back avoids shifting the remaining consoles
+        for (i, job) in self.jobs[..chunks].iter_mut().enumerate().rev() {
+            let start = i * per_thread;
+            job.consoles.extend(self.consoles.drain(start..));
+            job.buttons.clear();
+            job.buttons
+                .extend_from_slice(&buttons[start..start + job.consoles.len()]);
+        }
+        for (worker, job) in self.workers.iter().zip(&mut self.jobs[..chunks]) {
+            worker
+                .jobs
+                .send(mem::take(job))
+                .expect("ConsolePool worker stopped");
+        }
+        self.batch.samples.clear();
+        for (worker, job) in self.workers.iter().zip(&mut self.jobs[..chunks]) {
+            *job = worker.done.recv().expect("ConsolePool worker panicked");
+            let first = self.consoles.len();
+            let pixels = first * FRAME_PIXELS..(first + job.consoles.len()) * FRAME_PIXELS;
+            self.batch.pixels[pixels].copy_from_slice(&job.pixels);
+            let ranges = &mut self.batch.sample_ranges[first..];
+            for (samples, range) in job.audio.iter().zip(ranges) {
+                let start = self.batch.samples.len();
+                self.batch.samples.extend_from_slice(samples);
+                *range = start..self.batch.samples.len();
+            }
+            self.consoles.append(&mut job.consoles);
+        }
+        &self.batch
+    }
+
+    /// Resets every console in the pool.
+    pub fn reset(&mut self) {
+        for console in &mut self.consoles {
+            console.reset();
+        }
+    }
+
+    /// Takes a snapshot of every console in the pool.
+    pub fn save_states(&self) -> Vec<SaveState> {
+        self.consoles.iter().map(Console::save_state).collect()
+    }
+
+    /// Restores every console from a snapshot, in the same order as the pool.
+    ///
+    /// This will panic if there isn't exactly one state per console.
+    pub fn load_states(&mut self, states: &[SaveState]) {
+        assert_eq!(
+            states.len(),
+            self.consoles.len(),
+            "ConsolePool needs one SaveState per console"
+        );
+        for (console, state) in self.consoles.iter_mut().zip(states) {
+            console.load_state(state);
+        }
+    }
+
+    /// Restores every console from the same snapshot.
+    pub fn load_state_all(&mut self, state: &SaveState) {
+        for console in &mut self.consoles {
+            console.load_state(state);
+        }
+    }
+}
@@ -20,6 +20,7 @@ const BUFFER_PIXELS: usize = NES_WIDTH * NES_HEIGHT;
 ///
 /// This struct is somewhat large, so it should be boxed when included
 /// in another struct to avoid blowing up the stack.
+#[derive(Clone)]
 pub struct PixelBuffer([u32; BUFFER_PIXELS]);
 
 impl Default for PixelBuffer {
@@ -43,6 +44,89 @@ impl PixelBuffer {
     }
 }
 
+/// An image of any size, in the same format as `PixelBuffer`.
+///
+/// This is used for debug views of the PPU's memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// The pixels, row by row, in ARGB format
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    /// Creates a completely transparent image.
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Returns the pixel at a given position.
+    pub fn get(&self, x: usize, y: usize) -> u32 {
+        self.pixels[self.width * y + x]
+    }
+
+    pub(crate) fn set(&mut self, x: usize, y: usize, argb: u32) {
+        let index = self.width * y + x;
+        self.pixels[index] = argb;
+    }
+}
+
+impl AsRef<[u32]> for Image {
+    fn as_ref(&self) -> &[u32] {
+        &self.pixels
+    }
+}
+
+/// Which layers get drawn to the pixel buffer.
+///
+/// These only change the frames that come out of the console. The game
+/// still sees the PPU behave as usual, so hidden layers still cause
+/// sprite 0 hits, and the Zapper still sees what would have been drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layers {
+    pub background: bool,
+    pub sprites: bool,
+    /// The sprites hidden from the output, with a bit for each slot of OAM
+    pub hidden_sprites: u64,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers {
+            background: true,
+            sprites: true,
+            hidden_sprites: 0,
+        }
+    }
+}
+
+impl Layers {
+    /// Hides or shows the sprite in a slot of OAM, from 0 to 63.
+    pub fn set_sprite_visible(&mut self, slot: u8, visible: bool) {
+        let bit = 1 << (slot % 64);
+        if visible {
+            self.hidden_sprites &= !bit;
+        } else {
+            self.hidden_sprites |= bit;
+        }
+    }
+
+    /// Checks if the sprite in a slot of OAM gets drawn.
+    pub fn is_sprite_visible(&self, slot: u8) -> bool {
+        self.sprites && (self.hidden_sprites >> (slot % 64)) & 1 == 0
+    }
+
+    /// Checks if every layer is drawn, as usual.
+    pub fn all_visible(&self) -> bool {
+        *self == Layers::default()
+    }
+}
+
 /// This represents a video device we can write a pixel buffer to.
 /// 
 /// When implementing this trait, the device should be scaled to a factor
@@ -52,3 +136,14 @@ pub trait VideoDevice {
     /// Transfer a buffer of pixels onto this device.
     fn blit_pixels(&mut self, pixels: &PixelBuffer);
 }
+
+/// A device ignoring both audio and video, for internal use.
+pub(crate) struct NullDevice;
+
+impl AudioDevice for NullDevice {
+    fn push_sample(&mut self, _sample: f32) {}
+}
+
+impl VideoDevice for NullDevice {
+    fn blit_pixels(&mut self, _pixels: &PixelBuffer) {}
+}
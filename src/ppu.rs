@@ -1,74 +1,10 @@
+use super::events::EventKind;
 use super::memory::{Mapper, MemoryBus};
 
-use crate::ports::{PixelBuffer, VideoDevice};
-
-const PALETTE: [u32; 64] = [
-    0xFF75_7575,
-    0xFF27_1B8F,
-    0xFF00_00AB,
-    0xFF47_009F,
-    0xFF8F_0077,
-    0xFFAB_0013,
-    0xFFA7_0000,
-    0xFF7F_0B00,
-    0xFF43_2F00,
-    0xFF00_4700,
-    0xFF00_5100,
-    0xFF00_3F17,
-    0xFF1B_3F5F,
-    0xFF00_0000,
-    0xFF00_0000,
-    0xFF00_0000,
-    0xFFBC_BCBC,
-    0xFF00_73EF,
-    0xFF23_3BEF,
-    0xFF83_00F3,
-    0xFFBF_00BF,
-    0xFFE7_005B,
-    0xFFDB_2B00,
-    0xFFCB_4F0F,
-    0xFF8B_7300,
-    0xFF00_9700,
-    0xFF00_AB00,
-    0xFF00_933B,
-    0xFF00_838B,
-    0xFF00_0000,
-    0xFF00_0000,
-    0xFF00_0000,
-    0xFFFF_FFFF,
-    0xFF3F_BFFF,
-    0xFF5F_97FF,
-    0xFFA7_8BFD,
-    0xFFF7_7BFF,
-    0xFFFF_77B7,
-    0xFFFF_7763,
-    0xFFFF_9B3B,
-    0xFFF3_BF3F,
-    0xFF83_D313,
-    0xFF4F_DF4B,
-    0xFF58_F898,
-    0xFF00_EBDB,
-    0xFF00_0000,
-    0xFF00_0000,
-    0xFF00_0000,
-    0xFFFF_FFFF,
-    0xFFAB_E7FF,
-    0xFFC7_D7FF,
-    0xFFD7_CBFF,
-    0xFFFF_C7FF,
-    0xFFFF_C7DB,
-    0xFFFF_BFB3,
-    0xFFFF_DBAB,
-    0xFFFF_E7A3,
-    0xFFE3_FFA3,
-    0xFFAB_F3BF,
-    0xFFB3_FFCF,
-    0xFF9F_FFF3,
-    0xFF00_0000,
-    0xFF00_0000,
-    0xFF00_0000,
-];
+use crate::palette::Palette;
+use crate::ports::{Image, Layers, PixelBuffer, VideoDevice};
 
+#[derive(Clone)]
 struct NameTables([u8; 2048]);
 
 impl Default for NameTables {
@@ -87,6 +23,7 @@ impl NameTables {
     }
 }
 
+#[derive(Clone)]
 pub struct OAM(pub [u8; 256]);
 
 impl Default for OAM {
@@ -96,7 +33,7 @@ impl Default for OAM {
 }
 
 /// Represents openly modifiable PPU state
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct PPUState {
     // Memory
     palettes: [u8; 32],
@@ -388,7 +325,178 @@ impl PPUState {
     }
 }
 
+/// The color the scroll overlay is drawn with in the nametable view
+const SCROLL_OVERLAY: u32 = 0xFFFF_00FF;
+
+// Debug views, drawing the contents of the PPU's memory
+impl PPUState {
+    /// Returns the 32 bytes of palette RAM, as the PPU reads them.
+    pub fn palette_ram(&self) -> [u8; 32] {
+        let mut res = [0; 32];
+        for (i, entry) in res.iter_mut().enumerate() {
+            *entry = self.read_palette(i as u16);
+        }
+        res
+    }
+
+    /// Returns the color of an entry of palette RAM
+    fn palette_color(&self, colors: &Palette, entry: u16) -> u32 {
+        colors.color(self.read_palette(entry), 0)
+    }
+
+    /// Draws a row of 8 pixels from a tile, returning the 2 bit color of each
+    fn tile_row(&self, mapper: &dyn Mapper, address: u16) -> [u8; 8] {
+        let low = self.read(mapper, address);
+        let high = self.read(mapper, address + 8);
+        let mut row = [0; 8];
+        for (i, pixel) in row.iter_mut().enumerate() {
+            let shift = 7 - i;
+            *pixel = ((low >> shift) & 1) | (((high >> shift) & 1) << 1);
+        }
+        row
+    }
+
+    /// Draws both pattern tables side by side, in a 256x128 image.
+    ///
+    /// The palette is one of the 8 palettes, with 4 to 7 being for sprites.
+    pub fn pattern_tables_image(
+        &self,
+        mapper: &dyn Mapper,
+        colors: &Palette,
+        palette: u8,
+    ) -> Image {
+        let palette = u16::from(palette % 8) * 4;
+        let mut image = Image::new(256, 128);
+        for table in 0..2 {
+            for tile in 0..256 {
+                let x = table * 128 + (tile % 16) * 8;
+                let y = (tile / 16) * 8;
+                for row in 0..8 {
+                    let address = (table * 0x1000 + tile * 16 + row) as u16;
+                    for (col, &color) in self.tile_row(mapper, address).iter().enumerate() {
+                        let entry = if color == 0 { 0 } else { palette + u16::from(color) };
+                        image.set(x + col, y + row, self.palette_color(colors, entry));
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    /// Draws the four nametables, in a 512x480 image.
+    ///
+    /// The scroll overlay outlines the part of the nametables that will
+    /// be shown on screen, as of the latest scroll position.
+    pub fn nametables_image(
+        &self,
+        mapper: &dyn Mapper,
+        colors: &Palette,
+        scroll_overlay: bool,
+    ) -> Image {
+        let mut image = Image::new(512, 480);
+        let table = u16::from(self.flg_backgroundtable) * 0x1000;
+        for nametable in 0..4u16 {
+            let base = 0x2000 + nametable * 0x400;
+            let left = (nametable % 2) as usize * 256;
+            let top = (nametable / 2) as usize * 240;
+            for tile_y in 0..30u16 {
+                for tile_x in 0..32u16 {
+                    let tile = u16::from(self.read(mapper, base + tile_y * 32 + tile_x));
+                    let attribute_address = base + 0x3C0 + (tile_y / 4) * 8 + tile_x / 4;
+                    let shift = ((tile_y % 4) / 2) * 4 + ((tile_x % 4) / 2) * 2;
+                    let attribute = self.read(mapper, attribute_address);
+                    let palette = u16::from((attribute >> shift) & 3) * 4;
+                    for row in 0..8 {
+                        let pixels = self.tile_row(mapper, table + tile * 16 + row);
+                        for (col, &color) in pixels.iter().enumerate() {
+                            let entry = if color == 0 { 0 } else { palette + u16::from(color) };
+                            let x = left + tile_x as usize * 8 + col;
+                            let y = top + (tile_y * 8 + row) as usize;
+                            image.set(x, y, self.palette_color(colors, entry));
+                        }
+                    }
+                }
+            }
+        }
+        if scroll_overlay {
+            let (scroll_x, scroll_y) = self.scroll();
+            for i in 0..256 {
+                let x = (scroll_x + i) % 512;
+                image.set(x, scroll_y, SCROLL_OVERLAY);
+                image.set(x, (scroll_y + 239) % 480, SCROLL_OVERLAY);
+            }
+            for i in 0..240 {
+                let y = (scroll_y + i) % 480;
+                image.set(scroll_x, y, SCROLL_OVERLAY);
+                image.set((scroll_x + 255) % 512, y, SCROLL_OVERLAY);
+            }
+        }
+        image
+    }
+
+    /// Returns the scroll position, in pixels across all four nametables
+    fn scroll(&self) -> (usize, usize) {
+        let t = self.t as usize;
+        let nametable = (t >> 10) & 3;
+        let x = (nametable & 1) * 256 + (t & 0x1F) * 8 + self.x as usize;
+        let y = (nametable >> 1) * 240 + ((t >> 5) & 0x1F) * 8 + ((t >> 12) & 7);
+        (x, y % 480)
+    }
+
+    /// Draws all 64 sprites in OAM, in an 8 by 8 grid of 8x16 cells.
+    ///
+    /// This ignores the position of each sprite, but respects flipping.
+    /// In 8x8 mode, the bottom half of each cell stays transparent.
+    pub fn sprites_image(&self, mapper: &dyn Mapper, colors: &Palette) -> Image {
+        let mut image = Image::new(64, 128);
+        for i in 0..64 {
+            let tile = u16::from(self.oam.0[i * 4 + 1]);
+            let attributes = self.oam.0[i * 4 + 2];
+            let palette = 16 + u16::from(attributes & 3) * 4;
+            let flip_x = attributes & 0x40 != 0;
+            let flip_y = attributes & 0x80 != 0;
+            let (first, height) = if self.flg_spritesize == 0 {
+                (u16::from(self.flg_spritetable) * 0x1000 + tile * 16, 8)
+            } else {
+                ((tile & 1) * 0x1000 + (tile & 0xFE) * 16, 16)
+            };
+            for row in 0..height {
+                let source = if flip_y { height - 1 - row } else { row };
+                // The second tile of tall sprites comes right after the first
+                let address = first + (source / 8) * 16 + source % 8;
+                for (col, &color) in self.tile_row(mapper, address).iter().enumerate() {
+                    if color == 0 {
+                        continue;
+                    }
+                    let x = (i % 8) * 8 + if flip_x { 7 - col } else { col };
+                    let y = (i / 8) * 16 + row as usize;
+                    image.set(x, y, self.palette_color(colors, palette + u16::from(color)));
+                }
+            }
+        }
+        image
+    }
+
+    /// Draws the 32 entries of palette RAM as 8x8 squares, in a 128x16 image.
+    ///
+    /// The background palettes are on the first row, and the sprite palettes on the second.
+    pub fn palette_image(&self, colors: &Palette) -> Image {
+        let mut image = Image::new(128, 16);
+        for entry in 0..32 {
+            let color = self.palette_color(colors, entry);
+            let (left, top) = ((entry % 16) as usize * 8, (entry / 16) as usize * 8);
+            for y in top..top + 8 {
+                for x in left..left + 8 {
+                    image.set(x, y, color);
+                }
+            }
+        }
+        image
+    }
+}
+
 /// Represents the PPU
+#[derive(Clone)]
 pub(crate) struct PPU {
     cycle: i32,
     scanline: i32,
@@ -406,11 +514,18 @@ pub(crate) struct PPU {
     /// Even / odd frame flag (1 bit)
     f: u8,
     // Sprite temp variables
+    // Only the first 8 sprites are seen by the game, the rest are only drawn
     sprite_count: i32,
-    sprite_patterns: [u32; 8],
-    sprite_positions: [u8; 8],
-    sprite_priorities: [u8; 8],
-    sprite_indices: [u8; 8], //mem: Rc<RefCell<MemoryBus>>
+    sprite_patterns: [u32; 64],
+    sprite_positions: [u8; 64],
+    sprite_priorities: [u8; 64],
+    sprite_indices: [u8; 64], //mem: Rc<RefCell<MemoryBus>>
+    /// Which layers end up in the pixel buffer
+    pub layers: Layers,
+    /// Whether to only draw 8 sprites on each scanline, like the hardware
+    pub sprite_limit: bool,
+    /// The colors used to draw each pixel
+    pub palette: Palette,
 }
 
 impl PPU {
@@ -427,10 +542,13 @@ impl PPU {
             tiledata: 0,
             f: 0,
             sprite_count: 0,
-            sprite_patterns: [0; 8],
-            sprite_positions: [0; 8],
-            sprite_priorities: [0; 8],
-            sprite_indices: [0; 8],
+            sprite_patterns: [0; 64],
+            sprite_positions: [0; 64],
+            sprite_priorities: [0; 64],
+            sprite_indices: [0; 64],
+            layers: Layers::default(),
+            sprite_limit: true,
+            palette: Palette::default(),
         };
         ppu.reset(m);
         ppu
@@ -445,6 +563,13 @@ impl PPU {
         m.ppu.write_oam_address(0);
     }
 
+    /// Returns the frame currently being drawn.
+    ///
+    /// Right after vblank starts, this contains the last full frame.
+    pub fn pixels(&self) -> &PixelBuffer {
+        &self.v_buffer
+    }
+
     /// Used to clear vbuffers to make image completely neutral
     /// This isn't called in the standard reset.
     pub fn clear_vbuffers(&mut self) {
@@ -471,6 +596,7 @@ impl PPU {
         let tile = u16::from(self.nametable_byte);
         let address = 0x1000 * u16::from(table) + tile * 16 + fine_y;
         self.lowtile_byte = m.ppu.read(&*m.mapper, address);
+        m.log_pattern_fetch(address);
     }
 
     fn fetch_hightile_byte(&mut self, m: &mut MemoryBus) {
@@ -479,6 +605,7 @@ impl PPU {
         let tile = u16::from(self.nametable_byte);
         let address = 0x1000 * u16::from(table) + tile * 16 + fine_y;
         self.hightile_byte = m.ppu.read(&*m.mapper, address + 8);
+        m.log_pattern_fetch(address + 8);
     }
 
     fn store_tiledata(&mut self) {
@@ -495,7 +622,11 @@ impl PPU {
         self.tiledata |= u64::from(data);
     }
 
-    fn fetch_sprite_pattern(&self, m: &mut MemoryBus, i: usize, mut row: i32) -> u32 {
+    /// Fetches the pattern of a sprite, logging the fetch if `log` is set.
+    ///
+    /// Only the first 8 sprites on a line get fetched by the hardware,
+    /// so the extra sprites drawn without the limit shouldn't be logged.
+    fn fetch_sprite_pattern(&self, m: &mut MemoryBus, i: usize, mut row: i32, log: bool) -> u32 {
         let mut tile = m.ppu.oam.0[i * 4 + 1];
         let attributes = m.ppu.oam.0[i * 4 + 2];
         let address = if m.ppu.flg_spritesize == 0 {
@@ -519,6 +650,10 @@ impl PPU {
         let a = (attributes & 3) << 2;
         let mut lowtile_byte = m.ppu.read(&*m.mapper, address);
         let mut hightile_byte = m.ppu.read(&*m.mapper, address + 8);
+        if log {
+            m.log_pattern_fetch(address);
+            m.log_pattern_fetch(address + 8);
+        }
         let mut data: u32 = 0;
         for _ in 0..8 {
             let (p1, p2) = if attributes & 0x40 == 0x40 {
@@ -542,6 +677,7 @@ impl PPU {
 
     fn evaluate_sprites(&mut self, m: &mut MemoryBus) {
         let h: i32 = if m.ppu.flg_spritesize == 0 { 8 } else { 16 };
+        let limit = if self.sprite_limit { 8 } else { 64 };
         let mut count = 0;
         for i in 0..64 {
             let y = m.ppu.oam.0[i * 4];
@@ -551,8 +687,8 @@ impl PPU {
             if row < 0 || row >= h {
                 continue;
             }
-            if count < 8 {
-                let pattern = self.fetch_sprite_pattern(m, i, row);
+            if count < limit {
+                let pattern = self.fetch_sprite_pattern(m, i, row, count < 8);
                 self.sprite_patterns[count] = pattern;
                 self.sprite_positions[count] = x;
                 self.sprite_priorities[count] = (a_reg >> 5) & 1;
@@ -560,11 +696,11 @@ impl PPU {
             }
             count += 1;
         }
+        // Overflow is reported even when more than 8 sprites get drawn
         if count > 8 {
-            count = 8;
             m.ppu.flg_spriteoverflow = 1;
         }
-        self.sprite_count = count as i32;
+        self.sprite_count = count.min(limit) as i32;
     }
 
     fn set_vblank(&mut self, m: &mut MemoryBus, video: &mut impl VideoDevice) {
@@ -591,11 +727,17 @@ impl PPU {
         }
     }
 
-    fn sprite_pixel(&mut self, m: &mut MemoryBus) -> (u8, u8) {
+    /// Finds the first opaque sprite at the current dot, skipping hidden slots of OAM.
+    ///
+    /// Only the first `limit` sprites on the scanline are looked at.
+    fn sprite_pixel(&mut self, m: &mut MemoryBus, hidden: u64, limit: i32) -> (u8, u8) {
         if m.ppu.flg_showsprites == 0 {
             (0, 0)
         } else {
-            for i in 0..self.sprite_count {
+            for i in 0..self.sprite_count.min(limit) {
+                if (hidden >> self.sprite_indices[i as usize]) & 1 == 1 {
+                    continue;
+                }
                 let sp_off = i32::from(self.sprite_positions[i as usize]);
                 let mut offset = (self.cycle - 1) - sp_off;
                 if offset < 0 || offset > 7 {
@@ -614,41 +756,73 @@ impl PPU {
         }
     }
 
-    fn render_pixel(&mut self, m: &mut MemoryBus) {
-        let x = self.cycle - 1;
-        let y = self.scanline;
-        let mut background = self.background_pixel(m);
-        let (i, mut sprite) = self.sprite_pixel(m);
-        if x < 8 && m.ppu.flg_showleftbg == 0 {
-            background = 0;
-        }
-        if x < 8 && m.ppu.flg_showleftsprites == 0 {
-            sprite = 0;
-        }
-        let bg = background % 4 != 0;
-        let sp = sprite % 4 != 0;
-        let color = match (bg, sp) {
+    /// Picks between the background and a sprite, returning an index into palette RAM
+    fn mix_pixel(&self, background: u8, i: u8, sprite: u8) -> u8 {
+        let bg = !background.is_multiple_of(4);
+        let sp = !sprite.is_multiple_of(4);
+        match (bg, sp) {
             (false, false) => 0,
             (false, true) => sprite | 0x10,
             (true, false) => background,
             (true, true) => {
-                let ind = i as usize;
-                if self.sprite_indices[ind] == 0 && x < 255 {
-                    m.ppu.flg_sprite0hit = 1;
-                }
-                if self.sprite_priorities[ind] == 0 {
+                if self.sprite_priorities[i as usize] == 0 {
                     sprite | 0x10
                 } else {
                     background
                 }
             }
-        };
+        }
+    }
+
+    fn pixel_color(&self, m: &MemoryBus, color: u8) -> u32 {
         let mut color_index = m.ppu.read_palette(u16::from(color)) % 64;
         if m.ppu.flg_grayscale != 0 {
             color_index &= 0x30;
         }
-        let argb = PALETTE[color_index as usize];
-        self.v_buffer.write(x as usize, y as usize, argb);
+        let ppu = &m.ppu;
+        let emphasis = ppu.flg_redtint | (ppu.flg_greentint << 1) | (ppu.flg_bluetint << 2);
+        self.palette.color(color_index, emphasis)
+    }
+
+    fn render_pixel(&mut self, m: &mut MemoryBus) {
+        let x = self.cycle - 1;
+        let y = self.scanline;
+        let mut background = self.background_pixel(m);
+        let (i, mut sprite) = self.sprite_pixel(m, 0, 8);
+        let hide_left_sprites = x < 8 && m.ppu.flg_showleftsprites == 0;
+        if x < 8 && m.ppu.flg_showleftbg == 0 {
+            background = 0;
+        }
+        if hide_left_sprites {
+            sprite = 0;
+        }
+        let opaque = !background.is_multiple_of(4) && !sprite.is_multiple_of(4);
+        if opaque && self.sprite_indices[i as usize] == 0 && x < 255 && m.ppu.flg_sprite0hit == 0 {
+            m.ppu.flg_sprite0hit = 1;
+            if let Some(events) = &mut m.events.0 {
+                events.record(EventKind::Sprite0Hit);
+            }
+        }
+        let argb = self.pixel_color(m, self.mix_pixel(background, i, sprite));
+        // Hidden layers and extra sprites only change what gets drawn, not what the game sees
+        let extra_sprites = self.sprite_count > 8;
+        let shown = if self.layers.all_visible() && !extra_sprites {
+            argb
+        } else {
+            let layers = self.layers;
+            let background = if layers.background { background } else { 0 };
+            let (i, sprite) = match (layers.sprites, layers.hidden_sprites) {
+                (false, _) => (0, 0),
+                (true, 0) if !extra_sprites => (i, sprite),
+                (true, hidden) if !hide_left_sprites => self.sprite_pixel(m, hidden, 64),
+                (true, _) => (0, 0),
+            };
+            self.pixel_color(m, self.mix_pixel(background, i, sprite))
+        };
+        self.v_buffer.write(x as usize, y as usize, shown);
+        if m.senses_light() {
+            m.for_each_input(|device| device.sense_pixel(x, y, argb));
+        }
     }
 
     /// Steps the ppu forward
@@ -723,6 +897,9 @@ impl PPU {
             let was_nmi = m.ppu.nmi_output && m.ppu.nmi_occurred;
             if m.ppu.nmi_delay == 0 && was_nmi {
                 m.cpu.set_nmi();
+                if let Some(events) = &mut m.events.0 {
+                    events.record(EventKind::Nmi);
+                }
             }
         }
         let show_something = m.ppu.flg_showbg != 0 || m.ppu.flg_showsprites != 0;
@@ -731,17 +908,33 @@ impl PPU {
             self.cycle = 0;
             self.scanline = 0;
             self.f ^= 1;
+            self.log_position(m);
+            m.with_hooks((), |hooks, ctx| hooks.scanline(ctx, 0));
             return;
         }
 
         self.cycle += 1;
         if self.cycle > 340 {
+            m.for_each_input(|device| device.end_scanline());
             self.cycle = 0;
             self.scanline += 1;
             if self.scanline > 261 {
                 self.scanline = 0;
                 self.f ^= 1;
             }
+            let scanline = self.scanline as u16;
+            m.with_hooks((), |hooks, ctx| hooks.scanline(ctx, scanline));
+        }
+        self.log_position(m);
+    }
+
+    /// Tells the event log where the PPU is, starting a new frame on scanline 0
+    fn log_position(&self, m: &mut MemoryBus) {
+        if let Some(events) = &mut m.events.0 {
+            if self.scanline == 0 && self.cycle == 0 {
+                events.start_frame();
+            }
+            events.position = (self.scanline as u16, self.cycle as u16);
         }
     }
 }
@@ -0,0 +1,192 @@
+//! Searching memory for the addresses a game stores its variables at.
+//!
+//! A search starts with every address of work RAM and cartridge RAM as a
+//! candidate, and each filter removes the candidates whose value doesn't
+//! match, comparing against the values from the previous snapshot.
+use crate::console::Console;
+
+/// The size of the values being searched for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueSize {
+    Byte,
+    /// Two bytes, with the least significant byte first
+    Word,
+}
+
+/// How the bytes of a value are interpreted as a number
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Unsigned,
+    /// Two's complement
+    Signed,
+    /// Binary coded decimal, with a decimal digit in each nibble.
+    ///
+    /// Values with nibbles above 9 are never matched.
+    Bcd,
+}
+
+/// The comparisons that can be used to filter candidates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// The value is the same as in the previous snapshot
+    Equal,
+    /// The value is different from the previous snapshot
+    Changed,
+    /// The value is greater than in the previous snapshot
+    Increased,
+    /// The value is less than in the previous snapshot
+    Decreased,
+    /// The value changed by exactly this amount since the previous snapshot
+    ChangedBy(i64),
+    /// The value is exactly this one
+    Value(i64),
+}
+
+/// Where work RAM starts, ignoring its mirrors
+const RAM_START: u16 = 0x0000;
+const RAM_SIZE: u16 = 0x800;
+/// Where cartridge RAM starts
+const SRAM_START: u16 = 0x6000;
+const SRAM_SIZE: u16 = 0x2000;
+
+/// A search through memory, narrowing down candidate addresses.
+#[derive(Clone, Debug)]
+pub struct RamSearch {
+    size: ValueSize,
+    encoding: Encoding,
+    /// Work RAM, followed by cartridge RAM, as of the previous snapshot
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+/// Returns the position of an address inside of a snapshot
+fn snapshot_index(address: u16) -> usize {
+    if address >= SRAM_START {
+        (RAM_SIZE + address - SRAM_START) as usize
+    } else {
+        (address - RAM_START) as usize
+    }
+}
+
+/// Checks if a value of some size at an address fits inside of RAM or SRAM
+fn fits(address: u16, size: ValueSize) -> bool {
+    let last = match size {
+        ValueSize::Byte => address,
+        ValueSize::Word => address.saturating_add(1),
+    };
+    let in_region = |start: u16, len: u16| address >= start && last < start + len;
+    in_region(RAM_START, RAM_SIZE) || in_region(SRAM_START, SRAM_SIZE)
+}
+
+fn take_snapshot(console: &Console) -> Vec<u8> {
+    let mut snapshot = console.ram().to_vec();
+    snapshot.extend_from_slice(&console.sram());
+    snapshot
+}
+
+impl RamSearch {
+    /// Starts a new search, where every address is a candidate.
+    pub fn new(console: &Console, size: ValueSize, encoding: Encoding) -> Self {
+        let mut search = RamSearch {
+            size,
+            encoding,
+            snapshot: Vec::new(),
+            candidates: Vec::new(),
+        };
+        search.reset(console);
+        search
+    }
+
+    /// Makes every address a candidate again, and takes a new snapshot.
+    pub fn reset(&mut self, console: &Console) {
+        let ram = RAM_START..RAM_START + RAM_SIZE;
+        let sram = SRAM_START..SRAM_START + SRAM_SIZE;
+        let size = self.size;
+        self.candidates = ram.chain(sram).filter(|&a| fits(a, size)).collect();
+        self.snapshot = take_snapshot(console);
+    }
+
+    /// Updates the snapshot used for comparisons, without filtering.
+    pub fn snapshot(&mut self, console: &Console) {
+        self.snapshot = take_snapshot(console);
+    }
+
+    /// Removes every candidate not matching a filter, and takes a new snapshot.
+    ///
+    /// This returns the remaining candidates.
+    pub fn filter(&mut self, console: &Console, filter: Filter) -> &[u16] {
+        let current = take_snapshot(console);
+        let previous = &self.snapshot;
+        let size = self.size;
+        let encoding = self.encoding;
+        self.candidates.retain(|&address| {
+            let index = snapshot_index(address);
+            let new = decode(&current, index, size, encoding);
+            let old = decode(previous, index, size, encoding);
+            match (filter, new, old) {
+                (Filter::Value(value), Some(new), _) => new == value,
+                (Filter::Equal, Some(new), Some(old)) => new == old,
+                (Filter::Changed, Some(new), Some(old)) => new != old,
+                (Filter::Increased, Some(new), Some(old)) => new > old,
+                (Filter::Decreased, Some(new), Some(old)) => new < old,
+                (Filter::ChangedBy(delta), Some(new), Some(old)) => new - old == delta,
+                _ => false,
+            }
+        });
+        self.snapshot = current;
+        &self.candidates
+    }
+
+    /// Returns the addresses that still match every filter.
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// Returns the value at an address, as of the last snapshot.
+    ///
+    /// This returns None if the address isn't in RAM, or can't be
+    /// decoded, like BCD values with invalid digits.
+    pub fn value(&self, address: u16) -> Option<i64> {
+        if !fits(address, self.size) {
+            return None;
+        }
+        decode(
+            &self.snapshot,
+            snapshot_index(address),
+            self.size,
+            self.encoding,
+        )
+    }
+}
+
+/// Reads a value out of a snapshot
+fn decode(memory: &[u8], index: usize, size: ValueSize, encoding: Encoding) -> Option<i64> {
+    let bcd = |byte: u8| {
+        let (hi, lo) = (byte >> 4, byte & 0xF);
+        if hi > 9 || lo > 9 {
+            None
+        } else {
+            Some(i64::from(hi * 10 + lo))
+        }
+    };
+    match size {
+        ValueSize::Byte => {
+            let byte = *memory.get(index)?;
+            match encoding {
+                Encoding::Unsigned => Some(i64::from(byte)),
+                Encoding::Signed => Some(i64::from(byte as i8)),
+                Encoding::Bcd => bcd(byte),
+            }
+        }
+        ValueSize::Word => {
+            let lo = *memory.get(index)?;
+            let hi = *memory.get(index + 1)?;
+            let word = u16::from_le_bytes([lo, hi]);
+            match encoding {
+                Encoding::Unsigned => Some(i64::from(word)),
+                Encoding::Signed => Some(i64::from(word as i16)),
+                Encoding::Bcd => Some(bcd(hi)? * 100 + bcd(lo)?),
+            }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Loading labels from debug files, to show names instead of addresses.
+//!
+//! Labels can come from the `.dbg` files written by the ca65 linker,
+//! or from the `.nl` name lists used by FCEUX's debugger. Labels in ROM
+//! remember the offset into PRG they belong to, so that banks mapped
+//! to the same addresses don't get mixed up.
+//!
+//! More info: https://fceux.com/web/help/NLFilesFormat.html
+use std::collections::BTreeMap;
+
+use crate::console::Console;
+
+/// Represents the possible errors when loading labels
+#[derive(Clone, Debug, PartialEq)]
+pub enum SymbolError {
+    /// The file doesn't start with a version line, so it isn't a ca65 debug file
+    UnrecognisedFormat,
+    /// A line couldn't be parsed, with its line number
+    InvalidLine(usize),
+}
+
+/// The size of the PRG banks FCEUX uses to name its `.nl` files
+const NL_BANK_SIZE: usize = 0x4000;
+/// ca65 output offsets count the iNES header
+const INES_HEADER_SIZE: usize = 16;
+
+/// A name given to an address, or a range of addresses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub name: String,
+    /// The first CPU address this label covers
+    pub address: u16,
+    /// How many bytes this label covers, more than 1 for arrays
+    pub size: u16,
+    /// The offset into PRG ROM of the first byte, or None outside of ROM
+    pub prg_offset: Option<usize>,
+    pub comment: Option<String>,
+}
+
+impl Label {
+    /// Checks if this label covers an address, mapped to some offset of PRG.
+    ///
+    /// If the offset is unknown, only the address is compared.
+    fn covers(&self, address: u16, prg_offset: Option<usize>) -> bool {
+        let delta = address.wrapping_sub(self.address);
+        if delta >= self.size {
+            return false;
+        }
+        match (self.prg_offset, prg_offset) {
+            (Some(start), Some(offset)) => start + delta as usize == offset,
+            _ => true,
+        }
+    }
+}
+
+/// A collection of labels, loaded from one or more files.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    /// Every label, by the first address it covers
+    labels: BTreeMap<u16, Vec<Label>>,
+    /// The size of the largest label, to know how far back to look
+    max_size: u16,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    /// Adds a single label.
+    pub fn add(&mut self, label: Label) {
+        self.max_size = self.max_size.max(label.size);
+        self.labels.entry(label.address).or_default().push(label);
+    }
+
+    /// Returns every label, ordered by address.
+    pub fn labels(&self) -> impl Iterator<Item = &Label> {
+        self.labels.values().flatten()
+    }
+
+    /// Loads an FCEUX name list, returning how many labels were added.
+    ///
+    /// FCEUX uses one file per 16KB bank of PRG, named `game.nes.X.nl`,
+    /// where X is the bank in hexadecimal, as well as `game.nes.ram.nl`,
+    /// for everything outside of ROM, which should be loaded without a bank.
+    pub fn load_nl(&mut self, text: &str, bank: Option<usize>) -> Result<usize, SymbolError> {
+        let mut count = 0;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || SymbolError::InvalidLine(i + 1);
+            let mut parts = line.splitn(3, '#');
+            let location = parts.next().ok_or_else(invalid)?;
+            let name = parts.next().ok_or_else(invalid)?;
+            let comment = parts.next().filter(|c| !c.is_empty());
+            if !location.starts_with('$') {
+                return Err(invalid());
+            }
+            // Arrays are written as $address/size
+            let (address, size) = match location[1..].find('/') {
+                Some(slash) => (&location[1..=slash], &location[slash + 2..]),
+                None => (&location[1..], "1"),
+            };
+            let address = u16::from_str_radix(address, 16).map_err(|_| invalid())?;
+            let size = u16::from_str_radix(size, 16).map_err(|_| invalid())?;
+            // Lines with only a comment don't name anything
+            if name.is_empty() {
+                continue;
+            }
+            let prg_offset = match bank {
+                Some(bank) if address >= 0x8000 => {
+                    Some(bank * NL_BANK_SIZE + address as usize % NL_BANK_SIZE)
+                }
+                _ => None,
+            };
+            self.add(Label {
+                name: name.to_string(),
+                address,
+                size: size.max(1),
+                prg_offset,
+                comment: comment.map(|c| c.to_string()),
+            });
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Loads the labels from a ca65 debug file, returning how many were added.
+    ///
+    /// The offsets into PRG come from where the linker placed each segment
+    /// in the output file, which is assumed to start with an iNES header.
+    pub fn load_ca65_dbg(&mut self, text: &str) -> Result<usize, SymbolError> {
+        // Segments, by id, as (start address, offset in the output file)
+        let mut segments: BTreeMap<u32, (u32, Option<usize>)> = BTreeMap::new();
+        let mut labels = Vec::new();
+        let mut has_version = false;
+        for (i, line) in text.lines().enumerate() {
+            let invalid = || SymbolError::InvalidLine(i + 1);
+            let line = line.trim_end_matches('\r');
+            let (kind, fields) = match line.find(|c: char| c.is_whitespace()) {
+                Some(space) => (&line[..space], parse_dbg_fields(line[space..].trim_start())),
+                None => continue,
+            };
+            let field = |key: &str| fields.iter().find(|f| f.0 == key).map(|f| f.1);
+            let number = |key: &str| field(key).map(parse_dbg_number);
+            match kind {
+                "version" => has_version = true,
+                "seg" => {
+                    let id = number("id").ok_or_else(invalid)?.ok_or_else(invalid)?;
+                    let start = number("start").ok_or_else(invalid)?.ok_or_else(invalid)?;
+                    let ooffs = number("ooffs").map(|o| o.ok_or_else(invalid)).transpose()?;
+                    segments.insert(id, (start, ooffs.map(|o| o as usize)));
+                }
+                // Imports and constants don't have addresses of their own
+                "sym" if field("type") == Some("lab") => {
+                    let name = field("name").ok_or_else(invalid)?;
+                    let value = number("val").ok_or_else(invalid)?.ok_or_else(invalid)?;
+                    let size = number("size").unwrap_or(Some(1)).ok_or_else(invalid)?;
+                    let segment = number("seg").map(|s| s.ok_or_else(invalid)).transpose()?;
+                    labels.push((name.to_string(), value, size, segment));
+                }
+                _ => {}
+            }
+        }
+        if !has_version {
+            return Err(SymbolError::UnrecognisedFormat);
+        }
+        let count = labels.len();
+        for (name, value, size, segment) in labels {
+            let address = value as u16;
+            let placed = segment.and_then(|s| segments.get(&s));
+            let prg_offset = match placed {
+                Some(&(start, Some(ooffs))) if address >= 0x8000 => value
+                    .checked_sub(start)
+                    .and_then(|delta| (ooffs + delta as usize).checked_sub(INES_HEADER_SIZE)),
+                _ => None,
+            };
+            self.add(Label {
+                name,
+                address,
+                size: size.clamp(1, 0xFFFF) as u16,
+                prg_offset,
+                comment: None,
+            });
+        }
+        Ok(count)
+    }
+
+    /// Finds the label covering an address, mapped to an offset of PRG.
+    pub fn lookup(&self, address: u16, prg_offset: Option<usize>) -> Option<&Label> {
+        let lowest = address.saturating_sub(self.max_size.saturating_sub(1));
+        self.labels
+            .range(lowest..=address)
+            .rev()
+            .flat_map(|(_, labels)| labels)
+            .find(|label| label.covers(address, prg_offset))
+    }
+
+    /// Finds the label covering an address, in the bank a console has mapped there.
+    pub fn label_at(&self, console: &Console, address: u16) -> Option<&Label> {
+        self.lookup(address, console.prg_offset(address))
+    }
+
+    /// Returns the name to show for an address, with an offset for arrays,
+    /// like `buffer+3`, or None if no label covers it.
+    pub fn name_at(&self, console: &Console, address: u16) -> Option<String> {
+        self.label_at(console, address)
+            .map(|label| match address.wrapping_sub(label.address) {
+                0 => label.name.clone(),
+                delta => format!("{}+{}", label.name, delta),
+            })
+    }
+
+    /// Finds a label by name.
+    pub fn resolve(&self, name: &str) -> Option<&Label> {
+        self.labels().find(|label| label.name == name)
+    }
+
+    /// Resolves an address written by a user, like in a breakpoint.
+    ///
+    /// This accepts addresses in hexadecimal, starting with `$` or `0x`,
+    /// as well as label names, optionally followed by `+` or `-` and an offset.
+    pub fn resolve_address(&self, spec: &str) -> Option<u16> {
+        self.resolve_location(spec).map(|(address, _)| address)
+    }
+
+    /// Resolves an address like `resolve_address`, along with the offset
+    /// into PRG it belongs to, when it's relative to a label in ROM.
+    pub fn resolve_location(&self, spec: &str) -> Option<(u16, Option<usize>)> {
+        let spec = spec.trim();
+        let (base, delta) = match spec.rfind(['+', '-']) {
+            Some(sign) if sign > 0 => {
+                let delta = parse_number(spec[sign + 1..].trim())?;
+                let delta = if &spec[sign..=sign] == "-" {
+                    delta.wrapping_neg()
+                } else {
+                    delta
+                };
+                (spec[..sign].trim(), delta)
+            }
+            _ => (spec, 0),
+        };
+        let (base, prg_offset) = match parse_number(base) {
+            Some(address) if base.starts_with('$') || base.starts_with("0x") => (address, None),
+            _ => {
+                let label = self.resolve(base)?;
+                (label.address, label.prg_offset)
+            }
+        };
+        let prg_offset = prg_offset.and_then(|o| o.checked_add_signed(delta as i16 as isize));
+        Some((base.wrapping_add(delta), prg_offset))
+    }
+}
+
+/// Parses `$hex`, `0xhex`, or decimal numbers
+fn parse_number(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Parses numbers in ca65 debug files, written in decimal or as 0xhex
+fn parse_dbg_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Splits the `key=value` pairs of a line in a ca65 debug file.
+///
+/// Values can be quoted, in which case they may contain commas.
+fn parse_dbg_fields(text: &str) -> Vec<(&str, &str)> {
+    let mut fields = Vec::new();
+    let mut rest = text;
+    while let Some(equals) = rest.find('=') {
+        let key = &rest[..equals];
+        let after = &rest[equals + 1..];
+        let (value, next) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            let next = quoted[end..].find(',').map(|c| end + c + 1);
+            (&quoted[..end], next.map(|n| &quoted[n..]))
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            (&after[..end], after.get(end + 1..))
+        };
+        fields.push((key, value));
+        match next {
+            Some(next) => rest = next,
+            None => break,
+        }
+    }
+    fields
+}
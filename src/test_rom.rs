@@ -0,0 +1,119 @@
+//! Support for running accuracy test roms.
+//!
+//! Many test roms, like the ones written by blargg, report their results
+//! through cartridge RAM. Once the signature `DE B0 61` is present at $6001,
+//! the byte at $6000 holds the status of the test, and $6004 contains a
+//! null terminated text message.
+//!
+//! More info: https://github.com/christopherpow/nes-test-roms
+use crate::cart::Cart;
+use crate::console::Console;
+use crate::ports::NullDevice;
+
+/// The signature at $6001 indicating that the status is valid
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+/// The status written while the test is still running
+const STATUS_RUNNING: u8 = 0x80;
+/// The status written when the test needs the console to be reset
+const STATUS_NEEDS_RESET: u8 = 0x81;
+/// How many frames to wait before pressing reset, roughly 100ms
+const RESET_DELAY: u32 = 6;
+/// The sample rate used while running tests, which doesn't matter
+const SAMPLE_RATE: u32 = 44100;
+
+/// Represents how a test rom finished.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestStatus {
+    /// The test reported a result of 0
+    Passed,
+    /// The test reported a failure, with the given result code
+    Failed(u8),
+    /// The test never reported a final result
+    TimedOut,
+}
+
+/// The result of running a test rom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestReport {
+    /// How the test finished
+    pub status: TestStatus,
+    /// The text the test reported, which may be empty
+    pub message: String,
+    /// How many frames were run before the test finished
+    pub frames: u32,
+}
+
+impl TestReport {
+    /// Returns true if the test reported a success
+    pub fn passed(&self) -> bool {
+        self.status == TestStatus::Passed
+    }
+}
+
+/// Returns the status byte, if the signature is present
+fn read_status(console: &Console) -> Option<u8> {
+    let signature = [
+        console.peek(0x6001),
+        console.peek(0x6002),
+        console.peek(0x6003),
+    ];
+    if signature == SIGNATURE {
+        Some(console.peek(0x6000))
+    } else {
+        None
+    }
+}
+
+fn read_message(console: &Console) -> String {
+    let bytes: Vec<u8> = (0x6004..=0x7FFF)
+        .map(|address| console.peek(address))
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Runs a test rom until it reports a final result.
+///
+/// Tests asking for the console to be reset will be reset after a short
+/// delay. If no final result has been reported after `max_frames`, the
+/// test is considered to have timed out.
+pub fn run_test_rom(cart: Cart, max_frames: u32) -> TestReport {
+    let mut console = Console::new(cart, SAMPLE_RATE);
+    let mut reset_countdown = None;
+    for frame in 0..max_frames {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+        match read_status(&console) {
+            None | Some(STATUS_RUNNING) => {}
+            Some(STATUS_NEEDS_RESET) => {
+                let countdown = reset_countdown.unwrap_or(RESET_DELAY);
+                if countdown == 0 {
+                    console.reset();
+                    reset_countdown = None;
+                } else {
+                    reset_countdown = Some(countdown - 1);
+                }
+            }
+            Some(result) => {
+                let status = if result == 0 {
+                    TestStatus::Passed
+                } else {
+                    TestStatus::Failed(result)
+                };
+                return TestReport {
+                    status,
+                    message: read_message(&console),
+                    frames: frame + 1,
+                };
+            }
+        }
+    }
+    let message = match read_status(&console) {
+        Some(_) => read_message(&console),
+        None => String::new(),
+    };
+    TestReport {
+        status: TestStatus::TimedOut,
+        message,
+        frames: max_frames,
+    }
+}
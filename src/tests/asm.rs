@@ -0,0 +1,118 @@
+use super::super::cart::*;
+use std::sync::Arc;
+
+// Just enough of a 6502 assembler to write test programs
+pub(super) struct Program {
+    // The address the program will be placed at
+    origin: u16,
+    pub(super) bytes: Vec<u8>,
+}
+
+impl Program {
+    pub(super) fn new() -> Self {
+        Program::at(0x8000)
+    }
+
+    pub(super) fn at(origin: u16) -> Self {
+        Program {
+            origin,
+            bytes: Vec::new(),
+        }
+    }
+
+    // The address the next instruction will be placed at
+    pub(super) fn here(&self) -> u16 {
+        self.origin + self.bytes.len() as u16
+    }
+
+    pub(super) fn lda_imm(&mut self, value: u8) -> &mut Self {
+        self.bytes.extend_from_slice(&[0xA9, value]);
+        self
+    }
+
+    pub(super) fn lda(&mut self, address: u16) -> &mut Self {
+        let [hi, lo] = address.to_be_bytes();
+        self.bytes.extend_from_slice(&[0xAD, lo, hi]);
+        self
+    }
+
+    pub(super) fn sta(&mut self, address: u16) -> &mut Self {
+        let [hi, lo] = address.to_be_bytes();
+        self.bytes.extend_from_slice(&[0x8D, lo, hi]);
+        self
+    }
+
+    // Writes a sequence of bytes to a single register
+    pub(super) fn write_all(&mut self, register: u16, bytes: &[u8]) -> &mut Self {
+        for &byte in bytes {
+            self.lda_imm(byte).sta(register);
+        }
+        self
+    }
+
+    // Writes a sequence of bytes to PPU memory, through $2006 and $2007
+    pub(super) fn write_ppu(&mut self, address: u16, bytes: &[u8]) -> &mut Self {
+        self.write_all(0x2006, &address.to_be_bytes())
+            .write_all(0x2007, bytes)
+    }
+
+    // Fills tile 0 with color 1, and tile 1 with color 2
+    pub(super) fn solid_tiles(&mut self) -> &mut Self {
+        let mut tiles = [0; 32];
+        tiles[..8].fill(0xFF);
+        tiles[24..].fill(0xFF);
+        self.write_ppu(0x0000, &tiles)
+    }
+
+    pub(super) fn cmp_imm(&mut self, value: u8) -> &mut Self {
+        self.bytes.extend_from_slice(&[0xC9, value]);
+        self
+    }
+
+    pub(super) fn bne(&mut self, offset: i8) -> &mut Self {
+        self.bytes.extend_from_slice(&[0xD0, offset as u8]);
+        self
+    }
+
+    pub(super) fn jmp(&mut self, address: u16) -> &mut Self {
+        let [hi, lo] = address.to_be_bytes();
+        self.bytes.extend_from_slice(&[0x4C, lo, hi]);
+        self
+    }
+
+    // Loops forever at the current address
+    pub(super) fn hang(&mut self) -> &mut Self {
+        let here = self.here();
+        self.jmp(here)
+    }
+
+    // Writes the status, signature, and message to cartridge RAM
+    pub(super) fn report(&mut self, status: u8, message: &str) -> &mut Self {
+        for (i, &byte) in message.as_bytes().iter().chain(&[0]).enumerate() {
+            self.lda_imm(byte).sta(0x6004 + i as u16);
+        }
+        self.lda_imm(status).sta(0x6000);
+        self.lda_imm(0xDE).sta(0x6001);
+        self.lda_imm(0xB0).sta(0x6002);
+        self.lda_imm(0x61).sta(0x6003)
+    }
+
+    // Makes an NROM cart starting execution at the beginning of the program
+    pub(super) fn into_cart(self) -> Cart {
+        let mut prg = self.bytes;
+        prg.resize(0x4000, 0xEA);
+        // The reset vector
+        prg[0x3FFC] = 0x00;
+        prg[0x3FFD] = 0x80;
+        Cart {
+            prg: Arc::new(prg),
+            chr: Arc::new(vec![0; 0x2000]),
+            chr_ram: true,
+            sram: [0; 0x2000],
+            mapper: MapperID::M2,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            has_battery: false,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use super::super::*;
+
+const ROM: &[u8] = include_bytes!("../../test_roms/palette.nes");
+
+const INPUT_LOG: &str = "[Input]\n\
+LogKey:#Reset|Power|#P1 Up|P1 Down|P1 Left|P1 Right|P1 Start|P1 Select|P1 B|P1 A|#P2 Up|P2 Down|P2 Left|P2 Right|P2 Start|P2 Select|P2 B|P2 A|\n\
+|..|........|........|\n\
+|..|U..RS..A|........|\n\
+|r.|........|.D....B.|\n\
+[/Input]\n";
+
+/// Builds a zip archive, compressing every other file
+fn make_zip(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut central = Vec::new();
+    for (i, (name, contents)) in files.iter().enumerate() {
+        let (method, body) = if i % 2 == 0 {
+            (0u16, contents.as_bytes().to_vec())
+        } else {
+            let body = miniz_oxide::deflate::compress_to_vec(contents.as_bytes(), 6);
+            (8u16, body)
+        };
+        let offset = data.len() as u32;
+        let header = |sig: u32, out: &mut Vec<u8>| {
+            out.extend_from_slice(&sig.to_le_bytes());
+            if sig == 0x0201_4B50 {
+                out.extend_from_slice(&20u16.to_le_bytes());
+            }
+            out.extend_from_slice(&[20, 0, 0, 0]);
+            out.extend_from_slice(&method.to_le_bytes());
+            out.extend_from_slice(&[0; 8]);
+            out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+            if sig == 0x0201_4B50 {
+                out.extend_from_slice(&[0; 10]);
+                out.extend_from_slice(&offset.to_le_bytes());
+            }
+            out.extend_from_slice(name.as_bytes());
+        };
+        header(0x0403_4B50, &mut data);
+        data.extend_from_slice(&body);
+        header(0x0201_4B50, &mut central);
+    }
+    let central_offset = data.len() as u32;
+    data.extend_from_slice(&central);
+    data.extend_from_slice(&0x0605_4B50u32.to_le_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    data.extend_from_slice(&central_offset.to_le_bytes());
+    data.extend_from_slice(&[0, 0]);
+    data
+}
+
+fn rom_sha1() -> String {
+    sha1_smol::Sha1::from(&ROM[16..]).digest().to_string()
+}
+
+fn make_header(sha1: &str) -> String {
+    format!("MovieVersion BizHawk v2.0\nPlatform NES\nSHA1 {}\n", sha1)
+}
+
+#[test]
+fn bk2_import() {
+    let header = make_header(&rom_sha1().to_uppercase());
+    let bk2 = make_zip(&[
+        ("Header.txt", &header),
+        ("Input Log.txt", INPUT_LOG),
+        ("SyncSettings.json", "{}"),
+    ]);
+    let imported = Bk2::from_bytes(&bk2, ROM).unwrap();
+    assert_eq!(imported.sync_settings, "{}");
+    let movie = imported.movie;
+    assert_eq!(movie.header_value("Platform"), Some("NES"));
+    assert_eq!(movie.frames.len(), 3);
+    assert_eq!(movie.frames[0], MovieFrame::default());
+    let p1 = movie.frames[1].port1;
+    assert!(p1.up && p1.right && p1.start && p1.a && !p1.b);
+    assert!(movie.frames[2].reset);
+    assert!(movie.frames[2].port2.down && movie.frames[2].port2.b);
+}
+
+#[test]
+fn bk2_rom_mismatch() {
+    let header = make_header("0000");
+    let bk2 = make_zip(&[("Header.txt", &header), ("Input Log.txt", INPUT_LOG)]);
+    match Bk2::from_bytes(&bk2, ROM) {
+        Err(Bk2Error::RomMismatch { actual, .. }) => assert_eq!(actual, rom_sha1()),
+        other => panic!("Expected a rom mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn bk2_errors() {
+    let header = make_header(&rom_sha1());
+    let zapper = "LogKey:#Reset|Power|#P2 Zapper X|\n|..|0|\n";
+    let bk2 = make_zip(&[("Header.txt", &header), ("Input Log.txt", zapper)]);
+    assert_eq!(
+        Bk2::from_bytes(&bk2, ROM),
+        Err(Bk2Error::UnsupportedController("P2 Zapper X".to_string()))
+    );
+    let bk2 = make_zip(&[("Header.txt", &header)]);
+    assert_eq!(
+        Bk2::from_bytes(&bk2, ROM),
+        Err(Bk2Error::MissingFile("Input Log.txt"))
+    );
+    assert_eq!(
+        Bk2::from_bytes(b"not a zip", ROM),
+        Err(Bk2Error::InvalidArchive)
+    );
+}
+
+#[test]
+fn bk2_entries_cant_exceed_their_size() {
+    let header = make_header(&rom_sha1());
+    let mut bk2 = make_zip(&[("Header.txt", &header), ("Input Log.txt", INPUT_LOG)]);
+    // Declare the compressed input log as being much smaller than it is
+    let central = (0..bk2.len())
+        .filter(|&i| bk2[i..].starts_with(&[0x50, 0x4B, 0x01, 0x02]))
+        .nth(1)
+        .unwrap();
+    bk2[central + 24..central + 28].copy_from_slice(&16u32.to_le_bytes());
+    assert_eq!(Bk2::from_bytes(&bk2, ROM), Err(Bk2Error::InvalidArchive));
+}
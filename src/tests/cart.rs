@@ -26,7 +26,7 @@ fn make_ines(
         flag6 |= 0b100;
     }
     flag6 |= (mapper & 0x0F) << 4;
-    let flag7 = (mapper & 0xF0) << 4;
+    let flag7 = mapper & 0xF0;
     buffer.push(0x4E);
     buffer.push(0x45);
     buffer.push(0x53);
@@ -35,20 +35,12 @@ fn make_ines(
     buffer.push(chr_chunks as u8);
     buffer.push(flag6);
     buffer.push(flag7);
-    for _ in 8..16 {
-        buffer.push(0);
-    }
-    for _ in 0..trainer_offset {
-        buffer.push(0x1);
-    }
+    buffer.resize(buffer.len() + 8, 0);
+    buffer.resize(buffer.len() + trainer_offset, 0x1);
     buffer.push(0xFF);
-    for _ in 1..prg_chunks * 0x4000 {
-        buffer.push(0x2);
-    }
+    buffer.resize(buffer.len() + prg_chunks * 0x4000 - 1, 0x2);
     buffer.push(0xFF);
-    for _ in 1..chr_chunks * 0x2000 {
-        buffer.push(0x3);
-    }
+    buffer.resize(buffer.len() + chr_chunks * 0x2000 - 1, 0x3);
     buffer
 }
 
@@ -60,7 +52,26 @@ fn cart_decoding() {
     let cart = cart_res.unwrap(); // we just asserted, so it's ok
     assert_eq!(cart.prg[0], 0xFF);
     assert_eq!(cart.chr[0], 0xFF);
-    assert_eq!(cart.mapper, 1);
+    assert_eq!(cart.mapper, MapperID::M1);
     assert!(!cart.mirroring.is_vertical());
-    assert_eq!(cart.has_battery, true);
+    assert!(cart.has_battery);
+}
+
+#[test]
+fn cart_decoding_nes2_submapper() {
+    let mut buffer = make_ines(Mirroring::Vertical, false, false, 23, 2, 1);
+    // Mark the header as NES 2.0, with submapper 2
+    buffer[7] |= 0x08;
+    buffer[8] = 0x20;
+    let cart = Cart::from_bytes(&buffer).unwrap();
+    assert_eq!(cart.mapper, MapperID::M23);
+    assert_eq!(cart.submapper, 2);
+}
+
+#[test]
+fn cart_decoding_ines_ignores_submapper() {
+    let mut buffer = make_ines(Mirroring::Vertical, false, false, 23, 2, 1);
+    buffer[8] = 0x20;
+    let cart = Cart::from_bytes(&buffer).unwrap();
+    assert_eq!(cart.submapper, 0);
 }
@@ -0,0 +1,68 @@
+use super::super::cdl::*;
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+fn logging_console() -> Console {
+    let mut program = Program::new();
+    program.lda(0x9000).lda(0xC005);
+    // Point $20 at $9020, and read through it
+    program.lda_imm(0x20).sta(0x0020).lda_imm(0x90).sta(0x0021);
+    program.bytes.extend_from_slice(&[0xA0, 0x00, 0xB1, 0x20]);
+    // Read the first two bytes of CHR through $2007
+    program
+        .lda_imm(0)
+        .sta(0x2006)
+        .sta(0x2006)
+        .lda(0x2007)
+        .lda(0x2007);
+    // jmp ($9010)
+    program.bytes.extend_from_slice(&[0x6C, 0x10, 0x90]);
+    program.bytes.resize(0x100, 0xEA);
+    program.hang();
+    program.bytes.resize(0x1010, 0xEA);
+    program.bytes.extend_from_slice(&[0x00, 0x81]);
+    let mut cart = program.into_cart();
+    cart.chr_ram = false;
+    let mut console = Console::new(cart, 44100);
+    console.start_code_data_log();
+    console
+}
+
+#[test]
+fn cdl_logs_code_and_data() {
+    let mut console = logging_console();
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    let cdl = console.code_data_log().unwrap();
+    let prg = cdl.prg();
+    assert_eq!(prg.len(), 0x4000);
+    // lda $9000, and its operands
+    assert_eq!(prg[0], PRG_CODE);
+    assert_eq!(prg[2], PRG_CODE);
+    assert_eq!(prg[0x1000], PRG_DATA);
+    // Data read through $C000 remembers the window
+    assert_eq!(prg[0x0005], PRG_CODE | PRG_DATA | 0x08);
+    assert_eq!(prg[0x1020], PRG_DATA | PRG_INDIRECT_DATA);
+    assert_eq!(prg[0x1010], PRG_DATA);
+    assert_eq!(prg[0x0100], PRG_CODE | PRG_INDIRECT_CODE);
+    assert_eq!(prg[0x0200], 0);
+    assert_eq!(&cdl.chr()[..3], &[CHR_READ, CHR_READ, 0]);
+    let exported = cdl.to_cdl();
+    assert_eq!(exported.len(), 0x4000 + 0x2000);
+    assert_eq!(&exported[..0x4000], prg);
+}
+
+#[test]
+fn cdl_survives_loading_states() {
+    let mut console = logging_console();
+    let state = console.save_state();
+    assert!(console.clone().code_data_log().is_none());
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.load_state(&state);
+    assert_eq!(console.code_data_log().unwrap().prg()[0], PRG_CODE);
+    let cdl = console.stop_code_data_log().unwrap();
+    assert!(cdl.prg_coverage().0 > 0);
+    assert!(console.code_data_log().is_none());
+}
@@ -0,0 +1,102 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+use std::sync::Arc;
+
+#[test]
+fn game_genie_decoding() {
+    let cheat = Cheat::decode("SXIOPO").unwrap();
+    assert_eq!(
+        cheat,
+        Cheat::GameGenie {
+            address: 0x91D9,
+            value: 0xAD,
+            compare: None
+        }
+    );
+    let cheat = Cheat::decode("zexpygla").unwrap();
+    assert_eq!(
+        cheat,
+        Cheat::GameGenie {
+            address: 0x94A7,
+            value: 0x02,
+            compare: Some(0x03)
+        }
+    );
+    assert_eq!(Cheat::decode("SXIOPB"), Err(CheatError::InvalidLetter('B')));
+    // This is valid hex too, but only uses Game Genie letters
+    let cheat = Cheat::decode("AEAEAEAE").unwrap();
+    assert_eq!(
+        cheat,
+        Cheat::GameGenie {
+            address: 0x8088,
+            value: 0x08,
+            compare: Some(0x08)
+        }
+    );
+}
+
+#[test]
+fn freeze_decoding() {
+    let freeze = Cheat::Freeze {
+        address: 0x075A,
+        value: 0x09,
+    };
+    assert_eq!(Cheat::decode("075A:09"), Ok(freeze));
+    assert_eq!(Cheat::decode("00075A09"), Ok(freeze));
+    assert_eq!(
+        Cheat::decode("12345"),
+        Err(CheatError::UnrecognisedFormat("12345".to_string()))
+    );
+}
+
+// Copies the byte at $9000 to $10 forever, with $42 stored at $9000
+fn copy_rom_byte() -> Console {
+    let mut program = Program::new();
+    let start = program.here();
+    program.lda(0x9000).sta(0x0010).jmp(start);
+    let mut cart = program.into_cart();
+    Arc::make_mut(&mut cart.prg)[0x1000] = 0x42;
+    Console::new(cart, 44100)
+}
+
+#[test]
+fn game_genie_patches_reads() {
+    let mut console = copy_rom_byte();
+    // Writes $13 to $9000, if the original value is $42
+    let code = "LPAPAAZG";
+    assert_eq!(
+        Cheat::decode(code),
+        Ok(Cheat::GameGenie {
+            address: 0x9000,
+            value: 0x13,
+            compare: Some(0x42)
+        })
+    );
+    let index = console.cheats_mut().add(code).unwrap();
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    assert_eq!(console.peek(0x0010), 0x13);
+    console.cheats_mut().set_enabled(index, false);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    assert_eq!(console.peek(0x0010), 0x42);
+}
+
+#[test]
+fn freezes_are_applied_every_frame() {
+    let mut console = copy_rom_byte();
+    console.cheats_mut().add("0020:99").unwrap();
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    assert_eq!(console.peek(0x0020), 0x99);
+}
+
+#[test]
+fn cheats_are_saved_in_states() {
+    let mut console = copy_rom_byte();
+    console.cheats_mut().add("SXIOPO").unwrap();
+    let state = console.save_state();
+    console.cheats_mut().clear();
+    assert!(console.cheats().list().is_empty());
+    console.load_state(&state);
+    assert_eq!(console.cheats().list().len(), 1);
+}
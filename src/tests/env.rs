@@ -0,0 +1,69 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::make_console;
+
+fn make_actions() -> Vec<ButtonState> {
+    let right = ButtonState {
+        right: true,
+        ..Default::default()
+    };
+    vec![ButtonState::default(), right]
+}
+
+#[test]
+fn save_state_is_deterministic() {
+    let mut console = make_console();
+    for _ in 0..5 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    let state = console.save_state();
+    for _ in 0..5 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    let ram = console.ram().to_vec();
+    let pixels = console.pixels().as_ref().to_vec();
+    console.load_state(&state);
+    for _ in 0..5 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    assert_eq!(console.ram(), &ram[..]);
+    assert_eq!(console.pixels().as_ref(), &pixels[..]);
+}
+
+#[test]
+fn env_observation_sizes() {
+    let mut env = Env::new(make_console(), make_actions());
+    let rgb = env.reset();
+    assert_eq!(rgb.data.len(), NES_WIDTH * NES_HEIGHT * 3);
+    let mut env = env.with_observation(ObservationMode::Downsampled(4));
+    let (small, _) = env.step(1);
+    assert_eq!((small.width, small.height, small.channels), (64, 60, 1));
+    assert_eq!(small.data.len(), 64 * 60);
+}
+
+#[test]
+fn env_rewards_and_done() {
+    let mut frames = 0;
+    let mut env = Env::new(make_console(), make_actions())
+        .with_frame_skip(4)
+        .with_reward(|_| 1.0)
+        .with_done(move |_| {
+            frames += 1;
+            frames >= 6
+        });
+    env.reset();
+    let (_, info) = env.step(0);
+    assert_eq!(info.reward, 4.0);
+    assert!(!info.done);
+    let (_, info) = env.step(1);
+    assert_eq!(info.frames, 2);
+    assert!(info.done);
+}
+
+#[test]
+fn env_reset_restores_start() {
+    let mut env = Env::new(make_console(), make_actions()).with_frame_skip(10);
+    let first = env.reset();
+    env.step(1);
+    assert_eq!(env.reset(), first);
+}
@@ -0,0 +1,80 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+use std::sync::Arc;
+
+// Renders a solid background with sprite 0 on top, writing to the PPU
+// and to the mapper in its NMI handler
+fn logging_console() -> Console {
+    let mut program = Program::new();
+    // Make the low plane of tile 0 solid
+    program.lda_imm(0).sta(0x2006).sta(0x2006).lda_imm(0xFF);
+    for _ in 0..8 {
+        program.sta(0x2007);
+    }
+    // Turn on the frame IRQ, NMIs, and rendering, including the left column
+    program.lda_imm(0).sta(0x4017);
+    program.lda_imm(0x80).sta(0x2000);
+    program.lda_imm(0x1E).sta(0x2001);
+    program.hang();
+    program.bytes.resize(0x100, 0xEA);
+    // The NMI handler, writing to a mirror of $2005, and saving A
+    program.bytes.push(0x48);
+    program.lda_imm(0).sta(0x200D).sta(0x2005).sta(0x8000);
+    program.bytes.extend_from_slice(&[0x68, 0x40]);
+    let mut cart = program.into_cart();
+    let prg = Arc::make_mut(&mut cart.prg);
+    prg[0x3FFA] = 0x00;
+    prg[0x3FFB] = 0x81;
+    let mut console = Console::new(cart, 44100);
+    console.start_event_log();
+    console
+}
+
+#[test]
+fn events_are_tagged_with_their_position() {
+    let mut console = logging_console();
+    for _ in 0..3 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    let log = console.event_log().unwrap();
+    assert!(log.frames() >= 2);
+    let events = log.events();
+    let find = |kind: EventKind| events.iter().find(|e| e.kind == kind).cloned();
+
+    let nmi = find(EventKind::Nmi).expect("no NMI");
+    assert_eq!(nmi.scanline, 241);
+    let scroll = EventKind::PpuWrite {
+        register: 0x2005,
+        value: 0,
+    };
+    let writes: Vec<_> = events.iter().filter(|e| e.kind == scroll).collect();
+    assert_eq!(writes.len(), 2);
+    assert!(writes
+        .iter()
+        .all(|e| (e.scanline, e.dot) > (nmi.scanline, nmi.dot)));
+    let mapper = find(EventKind::MapperWrite {
+        address: 0x8000,
+        value: 0,
+    });
+    assert!(mapper.is_some());
+
+    let hit = find(EventKind::Sprite0Hit).expect("no sprite 0 hit");
+    assert_eq!(hit.scanline, 1);
+    assert!(find(EventKind::Irq).is_some());
+    assert_eq!(log.scanline_events(1).count(), 1);
+    // Events are in the order they happened
+    assert!(events
+        .windows(2)
+        .all(|w| (w[0].scanline, w[0].dot) <= (w[1].scanline, w[1].dot)));
+}
+
+#[test]
+fn stopping_returns_the_log() {
+    let mut console = logging_console();
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    assert!(console.stop_event_log().is_some());
+    assert!(console.event_log().is_none());
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    assert!(console.stop_event_log().is_none());
+}
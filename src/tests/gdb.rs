@@ -0,0 +1,138 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+use std::sync::Arc;
+
+struct Client(TcpStream);
+
+impl Client {
+    // Sends a packet, and returns the reply, acknowledging both
+    fn send(&mut self, data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+        write!(self.0, "${}#{:02x}", data, sum).unwrap();
+        assert_eq!(self.read_byte(), b'+');
+        self.reply()
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        self.read_byte();
+        self.read_byte();
+        self.0.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.0.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+// Serves a console running a small program, returning a client connected to it
+fn start() -> (Client, thread::JoinHandle<Console>) {
+    let mut program = Program::new();
+    program.lda_imm(5).sta(0x0010).lda_imm(7).sta(0x0011).hang();
+    serve(program.into_cart())
+}
+
+fn serve(cart: Cart) -> (Client, thread::JoinHandle<Console>) {
+    let mut console = Console::new(cart, 44100);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut server = GdbServer::new(stream);
+        server
+            .serve(&mut console, &mut NullDevice, &mut NullDevice)
+            .unwrap();
+        console
+    });
+    (Client(TcpStream::connect(address).unwrap()), server)
+}
+
+#[test]
+fn gdb_reads_and_writes_state() {
+    let (mut client, server) = start();
+    assert_eq!(client.send("?"), "S05");
+    assert!(client
+        .send("qSupported:multiprocess+")
+        .contains("qXfer:features:read+"));
+    assert!(client
+        .send("qXfer:features:read:target.xml:0,fff")
+        .starts_with("l<?xml"));
+    assert_eq!(client.send("g"), "00000024fd0080");
+    assert_eq!(client.send("P0=42"), "OK");
+    assert_eq!(client.send("p0"), "42");
+    assert_eq!(client.send("M20,2:abcd"), "OK");
+    assert_eq!(client.send("m20,2"), "abcd");
+    assert_eq!(client.send("M2000,1:00"), "E01");
+    assert_eq!(client.send("M6000,2:1234"), "OK");
+    assert_eq!(client.send("m6000,2"), "1234");
+    // Ranges larger than a packet are refused
+    assert_eq!(client.send("m0,800").len(), 0x1000);
+    assert_eq!(client.send("m0,801"), "E01");
+    assert_eq!(client.send("m0,ffffffffffff"), "E01");
+    assert_eq!(client.send("M0,801:00"), "E01");
+    assert_eq!(client.send("vMustReplyEmpty"), "");
+    assert_eq!(client.send("D"), "OK");
+    let console = server.join().unwrap();
+    assert_eq!(console.ram()[0x20], 0xAB);
+    assert_eq!(console.registers().a, 0x42);
+}
+
+#[test]
+fn gdb_writes_reach_the_mapper() {
+    // A UxROM cart, with every byte of a bank set to its index
+    let mut cart = Program::new().into_cart();
+    let prg = Arc::make_mut(&mut cart.prg);
+    prg.clear();
+    prg.resize(0x4000, 0x00);
+    prg.resize(0x8000, 0x01);
+    let (mut client, server) = serve(cart);
+    assert_eq!(client.send("m8000,1"), "00");
+    assert_eq!(client.send("M8000,1:01"), "OK");
+    assert_eq!(client.send("m8000,1"), "01");
+    assert_eq!(client.send("mc000,1"), "01");
+    client.0.write_all(b"$k#6b").unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn gdb_steps_and_breaks() {
+    let (mut client, server) = start();
+    assert_eq!(client.send("s"), "S05");
+    assert_eq!(client.send("p5"), "0280");
+    assert_eq!(client.send("Z0,8007,1"), "OK");
+    assert_eq!(client.send("c"), "S05");
+    assert_eq!(client.send("p5"), "0780");
+    assert_eq!(client.send("m10,2"), "0500");
+    assert_eq!(client.send("z0,8007,1"), "OK");
+    // Without breakpoints, this runs until interrupted
+    client.0.write_all(b"$c#63").unwrap();
+    assert_eq!(client.read_byte(), b'+');
+    client.0.write_all(&[0x03]).unwrap();
+    assert_eq!(client.reply(), "S05");
+    assert_eq!(client.send("m10,2"), "0507");
+    // Packets sent while running are answered once the console stops
+    client.0.write_all(b"$c#63").unwrap();
+    assert_eq!(client.read_byte(), b'+');
+    client.0.write_all(b"$p5#a5\x03").unwrap();
+    assert_eq!(client.reply(), "S05");
+    assert_eq!(client.read_byte(), b'+');
+    assert_eq!(client.reply(), "0a80");
+    client.0.write_all(b"$k#6b").unwrap();
+    let console = server.join().unwrap();
+    assert_eq!(console.registers().pc, 0x800A);
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+// Copies $10 to $11, and $12 to $13, forever
+fn copier() -> Console {
+    let mut program = Program::new();
+    let start = program.here();
+    program.lda(0x0010).sta(0x0011);
+    program.lda(0x0012).sta(0x0013).jmp(start);
+    Console::new(program.into_cart(), 44100)
+}
+
+fn step(console: &mut Console) {
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+}
+
+#[test]
+fn read_and_write_hooks_change_values() {
+    let mut console = copier();
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counter = reads.clone();
+    console
+        .hooks_mut()
+        .on_read(0x0010..=0x0010, move |_, _, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            0x42
+        });
+    console
+        .hooks_mut()
+        .on_write(0x0013..=0x0013, |ctx, _, value| {
+            ctx.poke(0x0020, 0x99);
+            value + 1
+        });
+    step(&mut console);
+    step(&mut console);
+    assert!(reads.load(Ordering::SeqCst) > 0);
+    assert_eq!(console.ram()[0x11], 0x42);
+    assert_eq!(console.ram()[0x13], 0x01);
+    assert_eq!(console.ram()[0x20], 0x99);
+}
+
+#[test]
+fn exec_hooks_change_registers() {
+    let mut console = copier();
+    // The second lda is at $8006, and the sta after it at $8009
+    console
+        .hooks_mut()
+        .on_exec(0x8009..=0x8009, |ctx, registers| {
+            assert_eq!(ctx.peek(0x0012), 0);
+            registers.a = 0x37;
+        });
+    step(&mut console);
+    step(&mut console);
+    assert_eq!(console.ram()[0x13], 0x37);
+}
+
+#[test]
+fn frame_and_scanline_hooks() {
+    let mut console = copier();
+    let starts = Arc::new(AtomicUsize::new(0));
+    let ends = Arc::new(AtomicUsize::new(0));
+    let scanlines = Arc::new(AtomicUsize::new(0));
+    let (s, e, l) = (starts.clone(), ends.clone(), scanlines.clone());
+    console.hooks_mut().on_frame_start(move |_| {
+        s.fetch_add(1, Ordering::SeqCst);
+    });
+    console.hooks_mut().on_frame_end(move |_| {
+        e.fetch_add(1, Ordering::SeqCst);
+    });
+    let id = console.hooks_mut().on_scanline(move |_, scanline| {
+        assert!(scanline < 262);
+        l.fetch_add(1, Ordering::SeqCst);
+    });
+    step(&mut console);
+    step(&mut console);
+    step(&mut console);
+    assert_eq!(starts.load(Ordering::SeqCst), 2);
+    assert_eq!(ends.load(Ordering::SeqCst), 3);
+    assert_eq!(scanlines.load(Ordering::SeqCst), 2 * 262 + 1);
+    assert!(console.hooks_mut().remove(id));
+    assert!(!console.hooks_mut().remove(id));
+    assert_eq!(console.hooks_mut().len(), 2);
+}
+
+#[test]
+fn hooks_survive_loading_states() {
+    let mut console = copier();
+    let state = console.save_state();
+    console.hooks_mut().on_read(0x0010..=0x0010, |_, _, _| 0x42);
+    console.load_state(&state);
+    step(&mut console);
+    step(&mut console);
+    assert_eq!(console.ram()[0x11], 0x42);
+    console.clear_hooks();
+    step(&mut console);
+    assert_eq!(console.ram()[0x11], 0);
+}
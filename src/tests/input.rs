@@ -0,0 +1,300 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+// Reads the controllers once, and then never again
+fn poll_once() -> Console {
+    let mut program = Program::new();
+    program.lda_imm(1).sta(0x4016).lda_imm(0).sta(0x4016);
+    for _ in 0..8 {
+        program.lda(0x4016);
+    }
+    program.lda(0x4017).hang();
+    Console::new(program.into_cart(), 44100)
+}
+
+#[test]
+fn lag_frames_are_counted() {
+    let mut console = poll_once();
+    // The console starts right before vblank, so the first frame is empty
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    assert!(console.last_frame_was_lag());
+    console.reset_lag_frames();
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    assert!(!console.last_frame_was_lag());
+    assert_eq!(console.last_frame_polls(), 9);
+    assert_eq!(console.last_frame_strobes(), 1);
+    assert_eq!(console.lag_frames(), 0);
+    for _ in 0..3 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+        assert!(console.last_frame_was_lag());
+    }
+    assert_eq!(console.last_frame_polls(), 0);
+    assert_eq!(console.last_frame_strobes(), 0);
+    assert_eq!(console.lag_frames(), 3);
+}
+
+// Fills the screen with white, and keeps copying $4017 to $10
+fn white_screen() -> Console {
+    let mut program = Program::new();
+    program.lda_imm(0x3F).sta(0x2006).lda_imm(0x00).sta(0x2006);
+    program.lda_imm(0x30).sta(0x2007);
+    program.lda_imm(0x0A).sta(0x2001);
+    let start = program.here();
+    program.lda(0x4017).sta(0x0010).jmp(start);
+    let mut console = Console::new(program.into_cart(), 44100);
+    console.set_zapper_connected(true);
+    console
+}
+
+fn zapper_bits(console: &mut Console) -> u8 {
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.peek(0x0010) & 0x18
+}
+
+#[test]
+fn zapper_senses_light() {
+    let mut console = white_screen();
+    // Right above vblank, the light from the last lines is still visible
+    console.aim_zapper(128, 230);
+    assert_eq!(zapper_bits(&mut console), 0x00);
+    console.set_zapper_trigger(true);
+    assert_eq!(zapper_bits(&mut console), 0x10);
+}
+
+#[test]
+fn zapper_light_fades() {
+    let mut console = white_screen();
+    console.aim_zapper(128, 10);
+    assert_eq!(zapper_bits(&mut console), 0x08);
+    console.aim_zapper(-1, 230);
+    assert_eq!(zapper_bits(&mut console), 0x08);
+    console.set_zapper_connected(false);
+    assert_eq!(zapper_bits(&mut console), 0x00);
+}
+
+// Remembers what was written to it, and returns every bit when read
+#[derive(Clone, Default)]
+struct TestDevice {
+    written: u8,
+    frames: u32,
+}
+
+impl InputDevice for TestDevice {
+    fn write(&mut self, value: u8) {
+        self.written = value;
+    }
+
+    fn read(&mut self) -> u8 {
+        0xFF
+    }
+
+    fn end_frame(&mut self) {
+        self.frames += 1;
+    }
+}
+
+#[test]
+fn custom_devices_can_be_plugged() {
+    let mut program = Program::new();
+    program.lda_imm(0x05).sta(0x4016);
+    program.lda(0x4016).sta(0x0010).lda(0x4017).sta(0x0011).hang();
+    let mut console = Console::new(program.into_cart(), 44100);
+    console.plug_port1(TestDevice::default());
+    console.plug_port2(Unplugged);
+    for _ in 0..3 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    // Only the 5 bits of the port can be read
+    assert_eq!(console.peek(0x0010), 0x1F);
+    assert_eq!(console.peek(0x0011), 0x00);
+    let device = console.port1_device::<TestDevice>().unwrap();
+    assert_eq!(device.written, 0x05);
+    assert_eq!(device.frames, 3);
+    assert!(console.port2_device::<Controller>().is_none());
+}
+
+// Strobes the ports, and then copies 24 reads from each to $20 and $40
+fn read_ports() -> Console {
+    let mut program = Program::new();
+    program.lda_imm(1).sta(0x4016).lda_imm(0).sta(0x4016);
+    for i in 0..24 {
+        program.lda(0x4016).sta(0x20 + i).lda(0x4017).sta(0x40 + i);
+    }
+    program.hang();
+    Console::new(program.into_cart(), 44100)
+}
+
+fn port_bits(console: &Console, start: u16, mask: u8) -> Vec<u8> {
+    (start..start + 24)
+        .map(|a| console.peek(a) & mask)
+        .map(|b| if b != 0 { 1 } else { 0 })
+        .collect()
+}
+
+fn four_players() -> [ButtonState; 4] {
+    let a = ButtonState {
+        a: true,
+        ..Default::default()
+    };
+    let b = ButtonState {
+        b: true,
+        ..Default::default()
+    };
+    let right = ButtonState {
+        right: true,
+        ..Default::default()
+    };
+    [a, b, right, ButtonState::default()]
+}
+
+#[test]
+fn four_score_reports_all_players() {
+    let mut console = read_ports();
+    console.set_multitap(Some(MultitapMode::FourScore));
+    console.update_multitap(four_players());
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    let first = port_bits(&console, 0x20, 1);
+    let mut expected = vec![1, 0, 0, 0, 0, 0, 0, 0];
+    expected.extend(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    expected.extend(&[0, 0, 0, 1, 0, 0, 0, 0]);
+    assert_eq!(first, expected);
+    let second = port_bits(&console, 0x40, 1);
+    let mut expected = vec![0, 1, 0, 0, 0, 0, 0, 0];
+    expected.extend(&[0, 0, 0, 0, 0, 0, 0, 0]);
+    expected.extend(&[0, 0, 1, 0, 0, 0, 0, 0]);
+    assert_eq!(second, expected);
+}
+
+#[test]
+fn hori_uses_second_data_line() {
+    let mut console = read_ports();
+    console.set_multitap(Some(MultitapMode::Hori));
+    console.update_multitap(four_players());
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    let first = port_bits(&console, 0x20, 1);
+    assert_eq!(&first[..8], &[1, 0, 0, 0, 0, 0, 0, 0]);
+    assert!(first[8..].iter().all(|&b| b == 1));
+    let third = port_bits(&console, 0x20, 2);
+    let mut expected = vec![0, 0, 0, 0, 0, 0, 0, 1];
+    expected.extend(&[0, 0, 0, 0, 0, 1, 0, 0]);
+    expected.extend(&[1; 8]);
+    assert_eq!(third, expected);
+    let fourth = port_bits(&console, 0x40, 2);
+    assert_eq!(&fourth[8..16], &[0, 0, 0, 0, 1, 0, 0, 0]);
+}
+
+// Turns a series of bits, most significant first, into a number
+fn from_bits(bits: &[u8]) -> u16 {
+    bits.iter().fold(0, |acc, &b| (acc << 1) | u16::from(b))
+}
+
+#[test]
+fn vaus_nes_wiring() {
+    let mut console = read_ports();
+    console.set_vaus(Some(VausWiring::Nes));
+    console.update_vaus(1.0, true);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    let fire = port_bits(&console, 0x40, 0x08);
+    assert!(fire.iter().all(|&b| b == 1));
+    let data = port_bits(&console, 0x40, 0x10);
+    // The position is sent inverted
+    assert_eq!(from_bits(&data[..8]) ^ 0xFF, 0xF2);
+    assert!(data[8..].iter().all(|&b| b == 1));
+}
+
+#[test]
+fn vaus_famicom_wiring() {
+    let mut console = read_ports();
+    console.set_vaus(Some(VausWiring::Famicom));
+    console.update_vaus(0.0, false);
+    let a = ButtonState {
+        a: true,
+        ..Default::default()
+    };
+    console.update_controller(a);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    // The controller is still plugged in
+    assert_eq!(port_bits(&console, 0x20, 1)[0], 1);
+    assert!(port_bits(&console, 0x20, 2).iter().all(|&b| b == 0));
+    let data = port_bits(&console, 0x40, 2);
+    assert_eq!(from_bits(&data[..9]) ^ 0x1FF, 0x62 << 1);
+    console.set_vaus(None);
+    assert!(console.expansion2_device::<Vaus>().is_none());
+}
+
+// Strobes a controller, and returns the 8 buttons it reports
+fn latch(controller: &mut Controller) -> ButtonState {
+    controller.write(1);
+    controller.write(0);
+    let mut bits = [false; 8];
+    for bit in &mut bits {
+        *bit = controller.read() == 1;
+    }
+    ButtonState::from_bits(bits)
+}
+
+#[test]
+fn turbo_toggles_every_frames() {
+    let mut controller = Controller::new();
+    controller.set_buttons(ButtonState {
+        a: true,
+        up: true,
+        ..Default::default()
+    });
+    controller.set_turbo(Some(Turbo::new(2)));
+    let mut pattern = Vec::new();
+    for _ in 0..8 {
+        let buttons = latch(&mut controller);
+        assert!(buttons.up);
+        pattern.push(buttons.a);
+        controller.end_frame();
+    }
+    assert_eq!(pattern, [true, true, false, false, true, true, false, false]);
+}
+
+#[test]
+fn turbo_toggles_on_strobes() {
+    let mut controller = Controller::new();
+    controller.set_buttons(ButtonState {
+        b: true,
+        ..Default::default()
+    });
+    controller.set_turbo(Some(Turbo {
+        clock: TurboClock::Strobes,
+        ..Turbo::new(1)
+    }));
+    // Two polls in the same frame see different states
+    let first = latch(&mut controller).b;
+    let second = latch(&mut controller).b;
+    assert_ne!(first, second);
+}
+
+#[test]
+fn macros_hold_buttons_for_frames() {
+    let right = ButtonState {
+        right: true,
+        ..Default::default()
+    };
+    let a = ButtonState {
+        a: true,
+        ..Default::default()
+    };
+    let steps = InputMacro::new().hold(right, 3).wait(1).hold(a, 1);
+    assert_eq!(steps.frames(), 5);
+    let mut controller = Controller::new();
+    controller.play_macro(steps);
+    let mut seen = Vec::new();
+    for _ in 0..6 {
+        seen.push(latch(&mut controller));
+        controller.end_frame();
+    }
+    let none = ButtonState::default();
+    assert_eq!(seen, [right, right, right, none, a, none]);
+    assert!(!controller.playing_macro());
+}
@@ -0,0 +1,79 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+// Draws a solid background, with two sprites on top of each other at (20, 11)
+fn console() -> Console {
+    let mut program = Program::new();
+    program
+        .write_ppu(0x3F00, &[0x0F, 0x16])
+        .write_ppu(0x3F11, &[0x11, 0x2A])
+        .solid_tiles()
+        // Every other sprite sits at the bottom, off screen
+        .write_all(0x2003, &[0x00])
+        .write_all(0x2004, &[10, 1, 0, 20, 10, 0, 0, 20]);
+    for _ in 2..64 {
+        program.write_all(0x2004, &[0xF0, 0, 0, 0]);
+    }
+    program
+        .write_all(0x2000, &[0x00])
+        .write_all(0x2005, &[0x00, 0x00])
+        .write_all(0x2001, &[0x1E])
+        .hang();
+    let mut console = Console::new(program.into_cart(), 44100);
+    for _ in 0..2 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    console
+}
+
+fn frame_pixel(console: &mut Console, x: usize, y: usize) -> u32 {
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.pixels().as_ref()[y * NES_WIDTH + x]
+}
+
+#[test]
+fn hiding_layers_changes_the_output() {
+    let mut console = console();
+    let colors = console.palette_image();
+    let (backdrop, background) = (colors.get(0, 0), colors.get(8, 0));
+    let (sprite1, sprite0) = (colors.get(8, 8), colors.get(16, 8));
+    assert!(console.layers().all_visible());
+    assert_eq!(frame_pixel(&mut console, 0, 11), background);
+    assert_eq!(frame_pixel(&mut console, 20, 11), sprite0);
+
+    console.layers_mut().set_sprite_visible(0, false);
+    assert!(!console.layers().is_sprite_visible(0));
+    assert_eq!(frame_pixel(&mut console, 20, 11), sprite1);
+
+    console.layers_mut().sprites = false;
+    assert_eq!(frame_pixel(&mut console, 20, 11), background);
+
+    *console.layers_mut() = Layers::default();
+    console.layers_mut().background = false;
+    assert_eq!(frame_pixel(&mut console, 0, 11), backdrop);
+    assert_eq!(frame_pixel(&mut console, 20, 11), sprite0);
+}
+
+#[test]
+fn hidden_sprites_still_hit() {
+    let mut console = console();
+    console.layers_mut().sprites = false;
+    console.layers_mut().background = false;
+    console.start_event_log();
+    for _ in 0..2 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    let events = console.event_log().unwrap().events();
+    let hit = events.iter().find(|e| e.kind == EventKind::Sprite0Hit);
+    assert_eq!(hit.map(|e| e.scanline), Some(11));
+}
+
+#[test]
+fn layers_are_kept_when_loading_states() {
+    let mut console = console();
+    let state = console.save_state();
+    console.layers_mut().background = false;
+    console.load_state(&state);
+    assert!(!console.layers().background);
+}
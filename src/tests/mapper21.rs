@@ -0,0 +1,103 @@
+use super::super::cart::*;
+use super::super::memory::Mapper;
+use std::sync::Arc;
+
+// Makes a cart where every byte of a bank contains the index of that bank,
+// using 8KB PRG banks, and 1KB CHR banks.
+fn make_cart(mapper: MapperID, submapper: u8) -> Cart {
+    let mut prg = Vec::with_capacity(16 * 0x2000);
+    for bank in 0..16 {
+        let end = prg.len() + 0x2000;
+        prg.resize(end, bank as u8);
+    }
+    let mut chr = Vec::with_capacity(256 * 0x400);
+    for bank in 0..256 {
+        let end = chr.len() + 0x400;
+        chr.resize(end, bank as u8);
+    }
+    Cart {
+        prg: Arc::new(prg),
+        chr: Arc::new(chr),
+        chr_ram: false,
+        sram: [0; 0x2000],
+        mapper,
+        submapper,
+        mirroring: Mirroring::Vertical,
+        has_battery: false,
+    }
+}
+
+#[test]
+fn vrc4_prg_banks() {
+    let mut mapper = <dyn Mapper>::with_cart(make_cart(MapperID::M23, 1));
+    assert_eq!(mapper.read(0x8000), 0);
+    assert_eq!(mapper.read(0xC000), 14);
+    assert_eq!(mapper.read(0xE000), 15);
+    mapper.write(0x8000, 3);
+    mapper.write(0xA000, 5);
+    assert_eq!(mapper.read(0x8000), 3);
+    assert_eq!(mapper.read(0xA000), 5);
+    // Swap mode moves the first switchable bank to $C000
+    mapper.write(0x9002, 2);
+    assert_eq!(mapper.read(0x8000), 14);
+    assert_eq!(mapper.read(0xC000), 3);
+}
+
+#[test]
+fn vrc4_chr_banks() {
+    let mut mapper = <dyn Mapper>::with_cart(make_cart(MapperID::M23, 1));
+    mapper.write(0xB002, 0x4);
+    mapper.write(0xB003, 0x2);
+    assert_eq!(mapper.read(0x0400), 0x24);
+    mapper.write(0xE002, 0xF);
+    assert_eq!(mapper.read(0x1C00), 0x0F);
+}
+
+#[test]
+fn submapper_address_lines() {
+    // VRC4e uses A2 and A3 instead of A0 and A1
+    let mut vrc4e = <dyn Mapper>::with_cart(make_cart(MapperID::M23, 2));
+    vrc4e.write(0xB004, 0x1);
+    assert_eq!(vrc4e.read(0x0000), 0x10);
+    vrc4e.write(0xB008, 0x3);
+    assert_eq!(vrc4e.read(0x0400), 0x03);
+    // VRC4b swaps A0 and A1
+    let mut vrc4b = <dyn Mapper>::with_cart(make_cart(MapperID::M25, 1));
+    vrc4b.write(0xB002, 0x1);
+    assert_eq!(vrc4b.read(0x0000), 0x10);
+    // VRC4c uses A6 and A7
+    let mut vrc4c = <dyn Mapper>::with_cart(make_cart(MapperID::M21, 2));
+    vrc4c.write(0xC080, 0x7);
+    assert_eq!(vrc4c.read(0x0C00), 0x07);
+}
+
+#[test]
+fn vrc2a_chr_ignores_low_bit() {
+    let mut mapper = <dyn Mapper>::with_cart(make_cart(MapperID::M22, 0));
+    mapper.write(0xB000, 0x5);
+    assert_eq!(mapper.read(0x0000), 0x2);
+}
+
+#[test]
+fn vrc4_mirroring() {
+    let mut mapper = <dyn Mapper>::with_cart(make_cart(MapperID::M25, 1));
+    mapper.write(0x9000, 1);
+    assert_eq!(mapper.mirroring_mode(), Mirroring::Horizontal);
+    mapper.write(0x9000, 3);
+    assert_eq!(mapper.mirroring_mode(), Mirroring::SingleUpper);
+}
+
+#[test]
+fn vrc4_cycle_irq() {
+    let mut mapper = <dyn Mapper>::with_cart(make_cart(MapperID::M23, 1));
+    mapper.write(0xF000, 0xE);
+    mapper.write(0xF001, 0xF);
+    // Enable in cycle mode
+    mapper.write(0xF002, 0x6);
+    assert!(!mapper.step());
+    assert!(mapper.step());
+    // The line stays asserted until acknowledged
+    assert!(mapper.step());
+    mapper.write(0xF003, 0);
+    assert!(!mapper.step());
+}
@@ -0,0 +1,85 @@
+use super::super::cart::*;
+use super::super::memory::Mapper;
+use std::sync::Arc;
+
+// Makes a 512KB cart where every byte of a 16KB bank contains its index
+fn make_cart(flashable: bool, mirroring: Mirroring) -> Cart {
+    let mut prg = Vec::with_capacity(32 * 0x4000);
+    for bank in 0..32 {
+        let end = prg.len() + 0x4000;
+        prg.resize(end, bank as u8);
+    }
+    Cart {
+        prg: Arc::new(prg),
+        chr: Arc::new(vec![0; 0x2000]),
+        chr_ram: true,
+        sram: [0; 0x2000],
+        mapper: MapperID::M30,
+        submapper: 0,
+        mirroring,
+        has_battery: flashable,
+    }
+}
+
+// Sends the unlock sequence, using the banks needed to reach the
+// command addresses
+fn unlock(mapper: &mut Box<dyn Mapper>) {
+    mapper.write(0xC000, 1);
+    mapper.write(0x9555, 0xAA);
+    mapper.write(0xC000, 0);
+    mapper.write(0xAAAA, 0x55);
+}
+
+// Sends the unlock sequence followed by a command
+fn send_command(mapper: &mut Box<dyn Mapper>, command: u8) {
+    unlock(mapper);
+    mapper.write(0xC000, 1);
+    mapper.write(0x9555, command);
+}
+
+#[test]
+fn unrom512_banks() {
+    let mut mapper = <dyn Mapper>::with_cart(make_cart(false, Mirroring::SingleLower));
+    assert_eq!(mapper.read(0xC000), 31);
+    mapper.write(0x8000, 0xC5);
+    assert_eq!(mapper.read(0x8000), 5);
+    assert_eq!(mapper.mirroring_mode(), Mirroring::SingleUpper);
+    // Each of the 4 CHR banks should be independent
+    mapper.write(0x0000, 0x12);
+    mapper.write(0x8000, 0x00);
+    assert_eq!(mapper.read(0x0000), 0);
+    mapper.write(0x8000, 0x40);
+    assert_eq!(mapper.read(0x0000), 0x12);
+    assert!(mapper.flash().is_none());
+}
+
+#[test]
+fn unrom512_flash_program_and_erase() {
+    let mut mapper = <dyn Mapper>::with_cart(make_cart(true, Mirroring::Vertical));
+    // Bank writes in the flash window shouldn't switch banks
+    mapper.write(0x8000, 3);
+    assert_eq!(mapper.read(0x8000), 0);
+    send_command(&mut mapper, 0x80);
+    unlock(&mut mapper);
+    mapper.write(0xC000, 2);
+    // Erase the first sector of bank 2
+    mapper.write(0x8000, 0x30);
+    assert_eq!(mapper.read(0x8FFF), 0xFF);
+    assert_eq!(mapper.read(0x9000), 2);
+    send_command(&mut mapper, 0xA0);
+    mapper.write(0xC000, 2);
+    mapper.write(0x8010, 0x42);
+    assert_eq!(mapper.read(0x8010), 0x42);
+    let flash = mapper.flash().unwrap();
+    assert_eq!(flash[2 * 0x4000 + 0x10], 0x42);
+}
+
+#[test]
+fn unrom512_software_id() {
+    let mut mapper = <dyn Mapper>::with_cart(make_cart(true, Mirroring::Vertical));
+    send_command(&mut mapper, 0x90);
+    assert_eq!(mapper.read(0x8000), 0xBF);
+    assert_eq!(mapper.read(0x8001), 0xB7);
+    mapper.write(0x8000, 0xF0);
+    assert_eq!(mapper.read(0x8000), 1);
+}
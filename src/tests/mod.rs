@@ -1 +1,32 @@
+mod asm;
+#[cfg(feature = "bk2")]
+mod bk2;
 mod cart;
+mod cdl;
+mod cheats;
+mod env;
+mod events;
+#[cfg(feature = "gdb")]
+mod gdb;
+mod hooks;
+mod input;
+mod layers;
+mod mapper21;
+mod mapper30;
+mod movie;
+mod palette;
+mod pool;
+mod ppu_views;
+mod ram_search;
+mod sprite_limit;
+mod symbols;
+mod test_rom;
+
+use super::{Cart, Console};
+
+// Makes a console running the palette test rom, shared by tests needing any game
+fn make_console() -> Console {
+    let rom = include_bytes!("../../test_roms/palette.nes");
+    let cart = Cart::from_bytes(rom).unwrap();
+    Console::new(cart, 44100)
+}
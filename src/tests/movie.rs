@@ -0,0 +1,76 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::make_console;
+
+const FM2: &str = "version 3\n\
+emuVersion 22020\n\
+romFilename game\n\
+port0 1\n\
+port1 0\n\
+port2 0\n\
+|0|R..U...A|||\n\
+|1|........|||\n\
+|0|.L..T.B.|||\n";
+
+#[test]
+fn fm2_parsing() {
+    let movie = Movie::from_fm2(FM2).unwrap();
+    assert_eq!(movie.header_value("romFilename"), Some("game"));
+    assert_eq!(movie.frames.len(), 3);
+    let first = movie.frames[0].port1;
+    assert!(first.right && first.up && first.a && !first.left);
+    assert!(movie.frames[1].reset);
+    let last = movie.frames[2].port1;
+    assert!(last.left && last.start && last.b && !last.a);
+    assert_eq!(movie.frames[2].port2, ButtonState::default());
+}
+
+#[test]
+fn fm2_round_trip() {
+    let mut movie = Movie::from_fm2(FM2).unwrap();
+    movie.frames[2].port2.select = true;
+    movie.frames[0].power = true;
+    let text = movie.to_fm2();
+    assert_eq!(Movie::from_fm2(&text).unwrap(), movie);
+}
+
+#[test]
+fn fm2_errors() {
+    assert_eq!(
+        Movie::from_fm2("romFilename game\n"),
+        Err(MovieError::UnrecognisedFormat)
+    );
+    assert_eq!(
+        Movie::from_fm2("version 3\nbinary 1\n"),
+        Err(MovieError::BinaryInput)
+    );
+    assert_eq!(
+        Movie::from_fm2("version 3\nport1 2\n"),
+        Err(MovieError::UnsupportedPort("port1 2".to_string()))
+    );
+    assert_eq!(
+        Movie::from_fm2("version 3\n|0|..|||\n"),
+        Err(MovieError::InvalidInput(2))
+    );
+}
+
+#[test]
+fn movie_playback_is_deterministic() {
+    let mut console = make_console();
+    let mut movie = Movie::new();
+    for i in 0..20 {
+        let buttons = ButtonState {
+            start: i % 4 == 0,
+            right: i % 3 == 0,
+            ..Default::default()
+        };
+        let mut frame = MovieFrame::new(buttons);
+        frame.reset = i == 10;
+        movie.record(frame, &mut console, &mut NullDevice, &mut NullDevice);
+    }
+    let ram = console.ram().to_vec();
+    let movie = Movie::from_fm2(&movie.to_fm2()).unwrap();
+    let mut replay = make_console();
+    movie.play(&mut replay, &mut NullDevice, &mut NullDevice);
+    assert_eq!(replay.ram(), &ram[..]);
+}
@@ -0,0 +1,118 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+#[test]
+fn default_palette_is_the_2c02() {
+    let palette = Palette::default();
+    assert_eq!(palette, Palette::builtin(BuiltinPalette::Ppu2C02));
+    assert_eq!(palette.colors().len(), 512);
+    assert_eq!(palette.color(0x16, 0), 0xFFDB_2B00);
+    assert_eq!(palette.color(0x30, 0), 0xFFFF_FFFF);
+    assert_eq!(palette.color(0x0F, 0), 0xFF00_0000);
+}
+
+#[test]
+fn builtin_palettes_are_distinct() {
+    let palettes = [
+        BuiltinPalette::Ppu2C02,
+        BuiltinPalette::Ppu2C03,
+        BuiltinPalette::Fceux,
+        BuiltinPalette::Smooth,
+    ];
+    for (i, &a) in palettes.iter().enumerate() {
+        for &b in &palettes[i + 1..] {
+            assert_ne!(Palette::builtin(a), Palette::builtin(b));
+        }
+    }
+    assert_eq!(
+        Palette::builtin(BuiltinPalette::Ppu2C03).color(0x16, 0),
+        Palette::builtin(BuiltinPalette::Ppu2C05).color(0x16, 0)
+    );
+    assert_eq!(
+        Palette::builtin(BuiltinPalette::Fceux).color(0x16, 0),
+        0xFFD8_2800
+    );
+}
+
+#[test]
+fn pal_files_with_64_colors_get_emphasis() {
+    let mut bytes = Vec::new();
+    for i in 0..64 {
+        bytes.extend_from_slice(&[i as u8, 100, 200]);
+    }
+    let palette = Palette::from_pal(&bytes).unwrap();
+    assert_eq!(palette.color(0x05, 0), 0xFF05_64C8);
+    // Red emphasis dims green and blue
+    assert_eq!(palette.color(0x20, 1), 0xFF20_4B95);
+    // Blue emphasis dims red and green
+    assert_eq!(palette.color(0x20, 4), 0xFF18_4BC8);
+    // The blacks of column $E aren't affected
+    assert_eq!(palette.color(0x0E, 7), 0xFF0E_64C8);
+}
+
+#[test]
+fn pal_files_with_512_colors_are_used_as_is() {
+    let bytes: Vec<u8> = (0..512 * 3).map(|i| (i % 251) as u8).collect();
+    let palette = Palette::from_pal(&bytes).unwrap();
+    let rgb = |i: usize| {
+        0xFF00_0000 | u32::from_be_bytes([0, bytes[i * 3], bytes[i * 3 + 1], bytes[i * 3 + 2]])
+    };
+    assert_eq!(palette.color(0x12, 0), rgb(0x12));
+    assert_eq!(palette.color(0x12, 5), rgb(5 * 64 + 0x12));
+}
+
+#[test]
+fn pal_files_must_have_64_or_512_colors() {
+    assert_eq!(
+        Palette::from_pal(&[0; 100]),
+        Err(PaletteError::InvalidSize(100))
+    );
+}
+
+#[test]
+fn rgb_ppus_max_out_emphasized_channels() {
+    let palette = Palette::builtin(BuiltinPalette::Ppu2C03);
+    assert_eq!(palette.color(0x0F, 0), 0xFF00_0000);
+    assert_eq!(palette.color(0x0F, 1), 0xFFFF_0000);
+    assert_eq!(palette.color(0x0F, 6), 0xFF00_FFFF);
+}
+
+// Shows the backdrop, in white, with red emphasis
+fn emphasis_console() -> Console {
+    let mut program = Program::new();
+    program.lda_imm(0x3F).sta(0x2006).lda_imm(0x00).sta(0x2006);
+    program.lda_imm(0x30).sta(0x2007);
+    program.lda_imm(0x00).sta(0x2006).sta(0x2006);
+    program.lda_imm(0x2A).sta(0x2001);
+    program.hang();
+    Console::new(program.into_cart(), 44100)
+}
+
+#[test]
+fn emphasis_changes_the_output() {
+    let mut console = emphasis_console();
+    for _ in 0..3 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    let pixel = console.pixels().as_ref()[100 * NES_WIDTH + 100];
+    assert_eq!(pixel, Palette::default().color(0x30, 1));
+    assert_ne!(pixel, Palette::default().color(0x30, 0));
+
+    let fceux = Palette::builtin(BuiltinPalette::Fceux);
+    console.set_palette(fceux.clone());
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    let pixel = console.pixels().as_ref()[100 * NES_WIDTH + 100];
+    assert_eq!(pixel, fceux.color(0x30, 1));
+    // Debug views use the palette too, without emphasis
+    assert_eq!(console.palette_image().get(0, 0), fceux.color(0x30, 0));
+}
+
+#[test]
+fn palettes_are_kept_when_loading_states() {
+    let mut console = emphasis_console();
+    let state = console.save_state();
+    console.set_palette(Palette::builtin(BuiltinPalette::Smooth));
+    console.load_state(&state);
+    assert_eq!(console.palette(), &Palette::builtin(BuiltinPalette::Smooth));
+}
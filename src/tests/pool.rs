@@ -0,0 +1,56 @@
+use super::super::*;
+use super::make_console;
+
+struct Frame<'a>(&'a mut Vec<u32>);
+impl VideoDevice for Frame<'_> {
+    fn blit_pixels(&mut self, pixels: &PixelBuffer) {
+        self.0.clear();
+        self.0.extend_from_slice(pixels.as_ref());
+    }
+}
+struct Samples<'a>(&'a mut Vec<f32>);
+impl AudioDevice for Samples<'_> {
+    fn push_sample(&mut self, sample: f32) {
+        self.0.push(sample);
+    }
+}
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn console_is_send() {
+    assert_send::<Console>();
+    assert_send::<SaveState>();
+}
+
+#[test]
+fn pool_matches_single_console() {
+    let mut single = make_console();
+    let mut frame = Vec::new();
+    let mut samples = Vec::new();
+    let consoles = (0..5).map(|_| make_console()).collect();
+    let mut pool = ConsolePool::new(consoles).with_threads(2);
+    let buttons = vec![ButtonState::default(); 5];
+    for _ in 0..3 {
+        samples.clear();
+        single.step_frame(&mut Samples(&mut samples), &mut Frame(&mut frame));
+        let batch = pool.step_frame(&buttons);
+        assert_eq!(batch.pixels.len(), 5 * NES_WIDTH * NES_HEIGHT);
+        for i in 0..5 {
+            assert_eq!(batch.frame(i), &frame[..]);
+            assert_eq!(batch.audio(i), &samples[..]);
+        }
+    }
+}
+
+#[test]
+fn pool_save_and_load() {
+    let consoles = (0..3).map(|_| make_console()).collect();
+    let mut pool = ConsolePool::new(consoles).with_threads(3);
+    let buttons = vec![ButtonState::default(); 3];
+    pool.step_frame(&buttons);
+    let states = pool.save_states();
+    let expected = pool.step_frame(&buttons).pixels.clone();
+    pool.load_states(&states);
+    assert_eq!(pool.step_frame(&buttons).pixels, expected);
+}
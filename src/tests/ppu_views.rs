@@ -0,0 +1,74 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+// Fills a few bytes of palette RAM, CHR, nametables and OAM
+fn console() -> Console {
+    let mut program = Program::new();
+    program
+        .write_ppu(0x3F00, &[0x0F, 0x16, 0x27, 0x30])
+        .write_ppu(0x3F11, &[0x11])
+        // The first row of tile 1 uses color 1
+        .write_ppu(0x0010, &[0xFF])
+        .write_ppu(0x2000, &[0x01])
+        .write_all(0x2003, &[0x00])
+        .write_all(0x2004, &[0x00, 0x01, 0x40, 0x00])
+        .write_all(0x2000, &[0x00])
+        .write_all(0x2005, &[0x08, 0x00])
+        .hang();
+    let mut console = Console::new(program.into_cart(), 44100);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+    console
+}
+
+#[test]
+fn palette_and_memory_are_visible() {
+    let console = console();
+    assert_eq!(&console.palette_ram()[..4], &[0x0F, 0x16, 0x27, 0x30]);
+    // $3F10 mirrors $3F00
+    assert_eq!(console.palette_ram()[16], 0x0F);
+    assert_eq!(console.palette_ram()[17], 0x11);
+    assert_eq!(&console.oam()[..4], &[0x00, 0x01, 0x40, 0x00]);
+    let palette = console.palette_image();
+    assert_eq!((palette.width, palette.height), (128, 16));
+    assert_eq!(palette.get(0, 8), palette.get(0, 0));
+    assert_ne!(palette.get(8, 0), palette.get(0, 0));
+}
+
+#[test]
+fn tiles_are_drawn_with_palettes() {
+    let console = console();
+    let colors = console.palette_image();
+    let backdrop = colors.get(0, 0);
+    let color1 = colors.get(8, 0);
+    let sprite_color1 = colors.get(8, 8);
+
+    let patterns = console.pattern_tables_image(0);
+    assert_eq!((patterns.width, patterns.height), (256, 128));
+    assert_eq!(patterns.get(8, 0), color1);
+    assert_eq!(patterns.get(15, 0), color1);
+    assert_eq!(patterns.get(8, 1), backdrop);
+    assert_eq!(console.pattern_tables_image(4).get(8, 0), sprite_color1);
+
+    let sprites = console.sprites_image();
+    assert_eq!((sprites.width, sprites.height), (64, 128));
+    assert_eq!(sprites.get(0, 0), sprite_color1);
+    assert_eq!(sprites.get(0, 1), 0);
+    assert_eq!(sprites.get(8, 0), 0);
+}
+
+#[test]
+fn nametables_show_scroll() {
+    let console = console();
+    let color1 = console.palette_image().get(8, 0);
+    let plain = console.nametables_image(false);
+    assert_eq!((plain.width, plain.height), (512, 480));
+    assert_eq!(plain.get(0, 0), color1);
+    assert_eq!(plain.get(8, 0), plain.get(0, 1));
+    let overlay = console.nametables_image(true);
+    assert_ne!(overlay.get(8, 100), plain.get(8, 100));
+    assert_ne!(overlay.get(100, 0), plain.get(100, 0));
+    assert_ne!(overlay.get(263, 100), plain.get(263, 100));
+    assert_eq!(overlay.get(7, 100), plain.get(7, 100));
+}
@@ -0,0 +1,70 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+// Every frame, increments a byte in RAM, and decrements a word in SRAM
+fn counter() -> Console {
+    let mut program = Program::new();
+    let start = program.here();
+    // Wait for vblank
+    program.lda(0x2002);
+    program.bytes.extend_from_slice(&[0x10, 0xFB]);
+    // inc $10
+    program.bytes.extend_from_slice(&[0xE6, 0x10]);
+    // dec $6001; dec $6000
+    program
+        .bytes
+        .extend_from_slice(&[0xCE, 0x01, 0x60, 0xCE, 0x00, 0x60]);
+    program.jmp(start);
+    Console::new(program.into_cart(), 44100)
+}
+
+fn step(console: &mut Console) {
+    console.step_frame(&mut NullDevice, &mut NullDevice);
+}
+
+#[test]
+fn filters_narrow_down_candidates() {
+    let mut console = counter();
+    step(&mut console);
+    let mut search = RamSearch::new(&console, ValueSize::Byte, Encoding::Unsigned);
+    assert_eq!(search.candidates().len(), 0x800 + 0x2000);
+    step(&mut console);
+    search.filter(&console, Filter::Changed);
+    assert!(search.candidates().contains(&0x0010));
+    assert!(search.candidates().contains(&0x6001));
+    step(&mut console);
+    search.filter(&console, Filter::Equal);
+    assert!(search.candidates().is_empty());
+    search.reset(&console);
+    step(&mut console);
+    search.filter(&console, Filter::Increased);
+    assert_eq!(search.candidates(), &[0x0010]);
+    let value = search.value(0x0010).unwrap();
+    step(&mut console);
+    assert_eq!(search.filter(&console, Filter::Value(value + 1)), &[0x0010]);
+    step(&mut console);
+    assert_eq!(search.filter(&console, Filter::ChangedBy(1)), &[0x0010]);
+}
+
+#[test]
+fn words_and_encodings() {
+    let mut console = counter();
+    step(&mut console);
+    let mut search = RamSearch::new(&console, ValueSize::Word, Encoding::Signed);
+    assert!(!search.candidates().contains(&0x07FF));
+    assert!(!search.candidates().contains(&0x7FFF));
+    assert_eq!(search.value(0x07FF), None);
+    assert_eq!(search.value(0x2000), None);
+    step(&mut console);
+    search.filter(&console, Filter::Decreased);
+    assert!(search.candidates().contains(&0x6000));
+    let before = search.value(0x6000).unwrap();
+    step(&mut console);
+    search.filter(&console, Filter::ChangedBy(-257));
+    assert!(search.candidates().contains(&0x6000));
+    assert_eq!(search.value(0x6000), Some(before - 257));
+    let bcd = RamSearch::new(&console, ValueSize::Word, Encoding::Bcd);
+    assert_eq!(bcd.value(0x6000), None);
+    assert_eq!(bcd.value(0x0100), Some(0));
+}
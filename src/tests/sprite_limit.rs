@@ -0,0 +1,100 @@
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+// Draws 10 sprites side by side at y = 11, on a solid background,
+// collecting the flags of $2002 in $10
+fn console() -> Console {
+    let mut program = Program::new();
+    program
+        .write_ppu(0x3F00, &[0x0F, 0x16])
+        .write_ppu(0x3F12, &[0x2A])
+        .solid_tiles()
+        .write_all(0x2003, &[0x00]);
+    for i in 0..64 {
+        let sprite = if i < 10 {
+            [10, 1, 0, i * 16]
+        } else {
+            [0xF0, 0, 0, 0]
+        };
+        program.write_all(0x2004, &sprite);
+    }
+    program
+        .write_all(0x2000, &[0x00])
+        .write_all(0x2005, &[0x00, 0x00])
+        .write_all(0x2001, &[0x1E]);
+    // lda $2002, ora $10, sta $10, in a loop
+    let here = program.here();
+    program
+        .bytes
+        .extend_from_slice(&[0xAD, 0x02, 0x20, 0x05, 0x10, 0x85, 0x10]);
+    program.jmp(here);
+    Console::new(program.into_cart(), 44100)
+}
+
+fn run(limit: bool) -> Console {
+    let mut console = console();
+    console.set_sprite_limit(limit);
+    for _ in 0..4 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    console
+}
+
+fn pixel(console: &Console, x: usize, y: usize) -> u32 {
+    console.pixels().as_ref()[y * NES_WIDTH + x]
+}
+
+#[test]
+fn sprite_limit_is_on_by_default() {
+    assert!(console().sprite_limit());
+}
+
+#[test]
+fn extra_sprites_are_drawn_without_the_limit() {
+    let limited = run(true);
+    let unlimited = run(false);
+    let colors = limited.palette_image();
+    let (background, sprite) = (colors.get(8, 0), colors.get(16, 8));
+    assert_eq!(pixel(&limited, 7 * 16, 11), sprite);
+    assert_eq!(pixel(&limited, 9 * 16, 11), background);
+    assert_eq!(pixel(&unlimited, 7 * 16, 11), sprite);
+    assert_eq!(pixel(&unlimited, 9 * 16, 11), sprite);
+}
+
+#[test]
+fn game_sees_the_same_flags_without_the_limit() {
+    let limited = run(true);
+    let unlimited = run(false);
+    // Both sprite 0 hit and sprite overflow were seen
+    assert_eq!(limited.ram()[0x10] & 0x60, 0x60);
+    assert_eq!(limited.ram(), unlimited.ram());
+}
+
+#[test]
+fn extra_sprites_are_not_logged() {
+    // Sprite i uses tile i + 1, on a cart with CHR ROM to log
+    let mut program = Program::new();
+    program.write_all(0x2003, &[0x00]);
+    for i in 0..64 {
+        let sprite = if i < 10 {
+            [10, i + 1, 0, i * 16]
+        } else {
+            [0xF0, 0, 0, 0]
+        };
+        program.write_all(0x2004, &sprite);
+    }
+    program.write_all(0x2001, &[0x10]).hang();
+    let mut cart = program.into_cart();
+    cart.chr_ram = false;
+    let mut console = Console::new(cart, 44100);
+    console.set_sprite_limit(false);
+    console.start_code_data_log();
+    for _ in 0..2 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    let chr = console.code_data_log().unwrap().chr();
+    assert_ne!(chr[8 * 16], 0);
+    assert_eq!(chr[9 * 16], 0);
+    assert_eq!(chr[10 * 16], 0);
+}
@@ -0,0 +1,115 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::super::ports::NullDevice;
+use super::super::*;
+use super::asm::Program;
+
+fn console() -> Console {
+    let mut program = Program::new();
+    program.hang();
+    Console::new(program.into_cart(), 44100)
+}
+
+const NL: &str = "$8000#Reset#Entry point\r
+$0200/10#Buffer#\r
+$0300##Just a comment\r
+$C010#Mirror#\r
+";
+
+#[test]
+fn nl_labels_are_bank_aware() {
+    let console = console();
+    let mut symbols = Symbols::new();
+    assert_eq!(symbols.load_nl(NL, Some(0)), Ok(3));
+    assert_eq!(symbols.load_nl("$8000#Other#", Some(1)), Ok(1));
+    assert_eq!(symbols.name_at(&console, 0x8000), Some("Reset".to_string()));
+    assert_eq!(
+        symbols.name_at(&console, 0xC010),
+        Some("Mirror".to_string())
+    );
+    assert_eq!(
+        symbols.name_at(&console, 0x0203),
+        Some("Buffer+3".to_string())
+    );
+    assert_eq!(symbols.name_at(&console, 0x0210), None);
+    assert_eq!(symbols.name_at(&console, 0x0300), None);
+    assert_eq!(symbols.lookup(0x8000, Some(0x4000)).unwrap().name, "Other");
+    let reset = symbols.resolve("Reset").unwrap();
+    assert_eq!(reset.prg_offset, Some(0));
+    assert_eq!(reset.comment, Some("Entry point".to_string()));
+    assert_eq!(
+        symbols.load_nl("8000#Bad#", None),
+        Err(SymbolError::InvalidLine(1))
+    );
+}
+
+#[test]
+fn addresses_resolve_through_labels() {
+    let mut symbols = Symbols::new();
+    symbols.load_nl(NL, None).unwrap();
+    assert_eq!(symbols.resolve_address("$8000"), Some(0x8000));
+    assert_eq!(symbols.resolve_address("0xC010"), Some(0xC010));
+    assert_eq!(symbols.resolve_address("Buffer + $2"), Some(0x0202));
+    assert_eq!(symbols.resolve_address("Reset-1"), Some(0x7FFF));
+    assert_eq!(symbols.resolve_address("Missing"), None);
+}
+
+const DBG: &str = "version\tmajor=2,minor=0
+file\tid=0,name=\"main, again.s\",size=100,mtime=0x5D000000,mod=0
+seg\tid=0,name=\"HEADER\",start=0x000000,size=0x0010,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=0
+seg\tid=1,name=\"CODE\",start=0x008000,size=0x4000,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=16
+seg\tid=2,name=\"BSS\",start=0x000300,size=0x0100,addrsize=absolute,type=rw
+sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=0,ref=1,val=0x8010,seg=1,type=lab
+sym\tid=1,name=\"lives\",addrsize=absolute,size=2,scope=0,def=1,val=0x300,seg=2,type=lab
+sym\tid=2,name=\"SPEED\",addrsize=zeropage,scope=0,def=2,val=0x3,type=equ
+sym\tid=3,name=\"ext\",addrsize=absolute,scope=0,def=3,type=imp,exp=0
+";
+
+#[test]
+fn ca65_labels_use_segment_offsets() {
+    let console = console();
+    let mut symbols = Symbols::new();
+    assert_eq!(symbols.load_ca65_dbg(DBG), Ok(2));
+    assert_eq!(symbols.resolve("reset").unwrap().prg_offset, Some(0x10));
+    assert_eq!(symbols.resolve("lives").unwrap().prg_offset, None);
+    assert_eq!(symbols.name_at(&console, 0xC010), None);
+    assert_eq!(symbols.name_at(&console, 0x8010), Some("reset".to_string()));
+    assert_eq!(
+        symbols.name_at(&console, 0x0301),
+        Some("lives+1".to_string())
+    );
+    assert!(symbols.resolve("SPEED").is_none());
+    assert_eq!(
+        symbols.load_ca65_dbg("sym\tid=0,name=\"a\",val=0x10,type=lab"),
+        Err(SymbolError::UnrecognisedFormat)
+    );
+}
+
+#[test]
+fn exec_hooks_can_use_labels() {
+    let mut program = Program::new();
+    program.lda_imm(1).sta(0x0010).hang();
+    let mut console = Console::new(program.into_cart(), 44100);
+    let mut symbols = Symbols::new();
+    symbols.load_nl("$8000#start#\n", Some(0)).unwrap();
+    symbols.load_nl("$8005#spin#\n", Some(1)).unwrap();
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let hooks = console.hooks_mut();
+    // The jmp of the loop is 5 bytes after the start
+    let hook = hooks.on_exec_label(&symbols, "start+5", move |_, registers| {
+        assert_eq!(registers.pc, 0x8005);
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    assert!(hook.is_some());
+    // This label is in a bank that's never mapped in
+    hooks.on_exec_label(&symbols, "spin", |_, _| panic!("wrong bank"));
+    assert!(hooks
+        .on_exec_label(&symbols, "missing", |_, _| {})
+        .is_none());
+    for _ in 0..2 {
+        console.step_frame(&mut NullDevice, &mut NullDevice);
+    }
+    assert!(hits.load(Ordering::SeqCst) > 1);
+}
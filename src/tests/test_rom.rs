@@ -0,0 +1,82 @@
+use super::super::cart::*;
+use super::super::test_rom::*;
+use super::asm::Program;
+
+use std::fs;
+use std::path::Path;
+
+/// How many frames the roms in the suite get to finish
+const SUITE_MAX_FRAMES: u32 = 60 * 60;
+
+#[test]
+fn test_rom_passing() {
+    let mut program = Program::new();
+    program.report(0x80, "").report(0, "All tests passed").hang();
+    let report = run_test_rom(program.into_cart(), 10);
+    assert!(report.passed());
+    assert_eq!(report.message, "All tests passed");
+}
+
+#[test]
+fn test_rom_failing() {
+    let mut program = Program::new();
+    program.report(3, "Failed #3").hang();
+    let report = run_test_rom(program.into_cart(), 10);
+    assert_eq!(report.status, TestStatus::Failed(3));
+    assert_eq!(report.message, "Failed #3");
+}
+
+#[test]
+fn test_rom_timeout() {
+    let mut program = Program::new();
+    program.report(0x80, "Still running").hang();
+    let report = run_test_rom(program.into_cart(), 10);
+    assert_eq!(report.status, TestStatus::TimedOut);
+    assert_eq!(report.message, "Still running");
+    assert_eq!(report.frames, 10);
+}
+
+#[test]
+fn test_rom_reset() {
+    // The program asks for a reset the first time, and passes the second
+    let mut program = Program::new();
+    program.lda(0x6010).cmp_imm(0x42);
+    // Skip over the jump to the passing code, on the first run
+    program.bne(3);
+    let mut first_run = Program::at(program.here() + 3);
+    first_run.lda_imm(0x42).sta(0x6010);
+    first_run.report(0x81, "Press reset").hang();
+    let pass = first_run.here();
+    program.jmp(pass);
+    program.bytes.extend_from_slice(&first_run.bytes);
+    program.report(0, "Passed after reset").hang();
+    let report = run_test_rom(program.into_cart(), 30);
+    assert!(report.passed(), "{:?}", report);
+    assert_eq!(report.message, "Passed after reset");
+}
+
+// Runs every rom placed in `test_roms/reporting`. These are our own roms,
+// using the same $6000 protocol as blargg's, and only check a few basics:
+// they aren't a substitute for the suites in nes-test-roms.
+#[test]
+fn reporting_roms_pass() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms/reporting");
+    let entries = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("couldn't read {}: {}", dir.display(), e));
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nes"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no roms in {}", dir.display());
+    let mut failures = Vec::new();
+    for path in paths {
+        let bytes = fs::read(&path).unwrap();
+        let cart = Cart::from_bytes(&bytes).unwrap();
+        let report = run_test_rom(cart, SUITE_MAX_FRAMES);
+        if !report.passed() {
+            failures.push(format!("{}: {:?}\n{}", path.display(), report.status, report.message));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
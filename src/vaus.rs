@@ -0,0 +1,123 @@
+use crate::input::InputDevice;
+
+/// The lowest value the potentiometer reports, with the knob turned left
+const NES_MIN: u16 = 0x62;
+/// The highest value the potentiometer reports, with the knob turned right
+const NES_MAX: u16 = 0xF2;
+
+/// The different ways the Vaus controller can be wired to the console
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VausWiring {
+    /// The NES version, plugged into the second port.
+    ///
+    /// The fire button is read through D3, and 8 bits of position through D4.
+    Nes,
+    /// The Famicom version, plugged into the expansion port.
+    ///
+    /// The fire button is read through D1 of $4016, and 9 bits of
+    /// position through D1 of $4017.
+    Famicom,
+}
+
+impl VausWiring {
+    fn position_bits(self) -> u8 {
+        match self {
+            VausWiring::Nes => 8,
+            VausWiring::Famicom => 9,
+        }
+    }
+}
+
+/// Represents the Vaus controller, the paddle used by Arkanoid.
+///
+/// The position of the knob is latched when the controller is strobed,
+/// and then sent serially, most significant bit first, with every bit inverted.
+/// The Famicom version is split across both registers, so it needs a half
+/// for each of them, like four player adapters.
+///
+/// More info: https://wiki.nesdev.com/w/index.php/Arkanoid_controller
+#[derive(Clone, Debug)]
+pub struct Vaus {
+    wiring: VausWiring,
+    /// Which register this half is read through, 0 for $4016, 1 for $4017
+    port: usize,
+    /// The current value of the potentiometer
+    position: u16,
+    fire: bool,
+    /// The position at the time of the last strobe, shifted out as it gets read
+    latched: u16,
+    strobe: bool,
+}
+
+impl Vaus {
+    /// Creates a new controller, read through $4016 if `port` is 0,
+    /// and through $4017 otherwise.
+    ///
+    /// The NES version is only read through $4017.
+    pub fn new(wiring: VausWiring, port: usize) -> Self {
+        let mut vaus = Vaus {
+            wiring,
+            port: port.min(1),
+            position: 0,
+            fire: false,
+            latched: 0,
+            strobe: false,
+        };
+        vaus.set_analog(0.5);
+        vaus
+    }
+
+    /// Sets the knob position, from 0.0 for fully left, to 1.0 for fully right.
+    pub fn set_analog(&mut self, position: f32) {
+        let position = position.clamp(0.0, 1.0);
+        let extra = self.wiring.position_bits() - 8;
+        let min = f32::from(NES_MIN << extra);
+        let max = f32::from(NES_MAX << extra);
+        self.position = (min + position * (max - min)).round() as u16;
+    }
+
+    /// Sets the raw value reported by the potentiometer.
+    pub fn set_position(&mut self, position: u16) {
+        let mask = (1 << self.wiring.position_bits()) - 1;
+        self.position = position & mask;
+    }
+
+    /// Returns the raw value reported by the potentiometer.
+    pub fn position(&self) -> u16 {
+        self.position
+    }
+
+    pub fn set_fire(&mut self, pressed: bool) {
+        self.fire = pressed;
+    }
+
+    /// Returns the next bit of the position, and shifts it out.
+    fn shift(&mut self) -> u8 {
+        let top = self.wiring.position_bits() - 1;
+        let bit = (self.latched >> top) & 1;
+        if !self.strobe {
+            self.latched <<= 1;
+        }
+        // The bits are sent inverted
+        (bit ^ 1) as u8
+    }
+}
+
+impl InputDevice for Vaus {
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 == 1;
+        if self.strobe {
+            self.latched = self.position;
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        let fire = u8::from(self.fire);
+        match (self.wiring, self.port) {
+            (VausWiring::Nes, 0) => 0,
+            (VausWiring::Nes, _) => (fire << 3) | (self.shift() << 4),
+            (VausWiring::Famicom, 0) => fire << 1,
+            (VausWiring::Famicom, _) => self.shift() << 1,
+        }
+    }
+}
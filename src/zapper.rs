@@ -0,0 +1,82 @@
+use crate::input::InputDevice;
+use crate::ports::{NES_HEIGHT, NES_WIDTH};
+
+/// How many pixels around the aim point the sensor can see
+const SENSOR_RADIUS: i32 = 2;
+/// How bright a pixel needs to be for the sensor to notice it
+const LIGHT_THRESHOLD: u32 = 0x80;
+/// How many scanlines the sensor stays on for after seeing light.
+///
+/// On hardware, this varies between 10 and 25 scanlines.
+const LIGHT_SCANLINES: u32 = 20;
+
+/// Represents a Zapper light gun, usually plugged into the second port.
+///
+/// The Zapper senses light by looking at the pixels the PPU draws
+/// under the point it's aimed at, as they get drawn.
+///
+/// More info: https://wiki.nesdev.com/w/index.php/Zapper
+#[derive(Clone, Debug, Default)]
+pub struct Zapper {
+    /// The pixel the zapper is aimed at, or None if aimed off screen
+    aim: Option<(i32, i32)>,
+    trigger: bool,
+    /// How many more scanlines the light sensor will stay on for
+    light: u32,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Zapper::default()
+    }
+
+    /// Aims the zapper at a given pixel on the screen.
+    ///
+    /// Coordinates outside of the screen mean that the zapper is
+    /// pointed away from it, and will never see any light.
+    pub fn aim(&mut self, x: i32, y: i32) {
+        let on_screen =
+            x >= 0 && y >= 0 && (x as usize) < NES_WIDTH && (y as usize) < NES_HEIGHT;
+        self.aim = if on_screen { Some((x, y)) } else { None };
+    }
+
+    /// Pulls or releases the trigger.
+    pub fn set_trigger(&mut self, pulled: bool) {
+        self.trigger = pulled;
+    }
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, _value: u8) {}
+
+    /// Lets the sensor look at a pixel as it gets drawn.
+    fn sense_pixel(&mut self, x: i32, y: i32, argb: u32) {
+        let (aim_x, aim_y) = match self.aim {
+            Some(aim) => aim,
+            None => return,
+        };
+        if (x - aim_x).abs() > SENSOR_RADIUS || (y - aim_y).abs() > SENSOR_RADIUS {
+            return;
+        }
+        let [_, r, g, b] = argb.to_be_bytes();
+        let brightness = (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000;
+        if brightness >= LIGHT_THRESHOLD {
+            self.light = LIGHT_SCANLINES;
+        }
+    }
+
+    fn senses_light(&self) -> bool {
+        true
+    }
+
+    fn end_scanline(&mut self) {
+        self.light = self.light.saturating_sub(1);
+    }
+
+    fn read(&mut self) -> u8 {
+        // The light bit is 0 when light is detected
+        let light = if self.light > 0 { 0 } else { 0x08 };
+        let trigger = if self.trigger { 0x10 } else { 0 };
+        light | trigger
+    }
+}
@@ -0,0 +1,86 @@
+; A small test rom reporting through $6000, like blargg's test roms.
+; It checks a few CPU behaviors, failing with the number of the first
+; check that doesn't hold.
+;
+; Built as NROM, with 16KB of PRG and 8KB of empty CHR.
+
+.segment "CODE"
+
+reset:
+    sei
+    cld
+    ldx #$FF
+    txs
+    ; Mark the test as running, and write the signature
+    lda #$80
+    sta $6000
+    lda #$DE
+    sta $6001
+    lda #$B0
+    sta $6002
+    lda #$61
+    sta $6003
+
+    ; 1: adding into bit 7 sets overflow and negative
+    ldx #1
+    lda #$7F
+    clc
+    adc #$01
+    bvc fail
+    bpl fail
+    cmp #$80
+    bne fail
+
+    ; 2: subtracting past 0 borrows
+    ldx #2
+    sec
+    lda #$00
+    sbc #$01
+    bcs fail
+    cmp #$FF
+    bne fail
+
+    ; 3: work RAM is mirrored every 2KB
+    ldx #3
+    lda #$5A
+    sta $0010
+    lda $0810
+    cmp #$5A
+    bne fail
+
+    ; 4: pushing and pulling from the stack
+    ldx #4
+    lda #$33
+    pha
+    lda #$00
+    pla
+    cmp #$33
+    bne fail
+
+    ldy #0
+copy:
+    lda passed,y
+    sta $6004,y
+    beq done
+    iny
+    bne copy
+done:
+    lda #$00
+    sta $6000
+hang:
+    jmp hang
+
+fail:
+    lda #$00
+    sta $6004
+    stx $6000
+    jmp hang
+
+nmi:
+    rti
+
+passed:
+    .byte "Passed", 0
+
+.segment "VECTORS"
+    .word nmi, reset, nmi